@@ -9,42 +9,81 @@
  *
  *****************************************************************************/
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{CustomCategory, DuplicateMode, NameNormalization};
 
 pub const CMD_PREFIX: &str = "/";
 
 pub type CommandMap = HashMap<String, String>;
 pub type ModuleMap = HashMap<String, CommandMap>;
 
+#[derive(Clone)]
 pub struct Category {
     pub is_visible: bool,
+    /// False for commands that are only known from descriptions.
+    pub is_registered: bool,
     pub name: String,
     pub modules: ModuleMap,
+    /// Module -> category it comes from, for modules moved here by `regroup`.
+    pub sources: HashMap<String, String>,
 }
 
 impl Category {
     pub fn new(name: String) -> Self {
         Self {
             is_visible: false,
+            is_registered: true,
             name,
             modules: ModuleMap::new(),
+            sources: HashMap::new(),
         }
     }
+
+    /// Where the commands of the module come from, SA-MP, SF, CLEO, Lua or another plugin.
+    pub fn source_of(&self, module: &str) -> &str {
+        self.sources.get(module).unwrap_or(&self.name)
+    }
 }
 
+/// Built-in categories are saved by their names, the indices may differ next time and are never saved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum CategoryKey {
     Samp,
     SfPlugin,
     Cleo,
     Lua,
+    Documented,
+    /// Index in `Categories::custom`.
+    #[serde(skip)]
+    Custom(usize),
+    /// Index in `Categories::external`.
+    #[serde(skip)]
+    External(usize),
+}
+
+impl CategoryKey {
+    pub fn is_builtin(self) -> bool {
+        !matches!(self, CategoryKey::Custom(_) | CategoryKey::External(_))
+    }
 }
 
+#[derive(Clone)]
 pub struct Categories {
-    pub order: [CategoryKey; 4],
+    pub order: Vec<CategoryKey>,
     pub samp: Category,
     pub sf: Category,
     pub cleo: Category,
     pub lua: Category,
+    pub documented: Category,
+    /// Defined by the user, filled only by `regroup`.
+    pub custom: Vec<Category>,
+    /// Registered by other plugins through the exported API, never removed to keep the indices.
+    pub external: Vec<Category>,
+    /// Command -> other categories that have it, filled only by `merge_duplicates`.
+    pub duplicates: HashMap<String, Vec<String>>,
 }
 
 impl Categories {
@@ -53,6 +92,8 @@ impl Categories {
             && self.sf.modules.is_empty()
             && self.cleo.modules.is_empty()
             && self.lua.modules.is_empty()
+            && self.custom.iter().all(|c| c.modules.is_empty())
+            && self.external.iter().all(|c| c.modules.is_empty())
     }
 
     pub fn category_count(&self) -> usize {
@@ -65,6 +106,271 @@ impl Categories {
             current_index: 0,
         }
     }
+
+    /// Categories in their order together with their keys.
+    pub fn keyed(&self) -> impl Iterator<Item = (CategoryKey, &Category)> {
+        self.order.iter().map(|key| (*key, &self[key]))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Category> {
+        [
+            &mut self.samp,
+            &mut self.sf,
+            &mut self.cleo,
+            &mut self.lua,
+            &mut self.documented,
+        ]
+        .into_iter()
+        .chain(self.custom.iter_mut())
+        .chain(self.external.iter_mut())
+    }
+
+    fn category_mut(&mut self, key: CategoryKey) -> &mut Category {
+        match key {
+            CategoryKey::Samp => &mut self.samp,
+            CategoryKey::SfPlugin => &mut self.sf,
+            CategoryKey::Cleo => &mut self.cleo,
+            CategoryKey::Lua => &mut self.lua,
+            CategoryKey::Documented => &mut self.documented,
+            CategoryKey::Custom(i) => &mut self.custom[i],
+            CategoryKey::External(i) => &mut self.external[i],
+        }
+    }
+
+    /// Replaces the commands of an external source, no commands hide it.
+    pub fn set_external(&mut self, name: &str, commands: CommandMap) {
+        let index = match self.external.iter().position(|c| c.name == name) {
+            Some(index) => index,
+            None => {
+                self.external.push(Category::new(name.to_string()));
+                // Before the documented commands, like the other registered ones.
+                let position = self
+                    .order
+                    .iter()
+                    .position(|&key| key == CategoryKey::Documented)
+                    .unwrap_or(self.order.len());
+                self.order
+                    .insert(position, CategoryKey::External(self.external.len() - 1));
+                self.external.len() - 1
+            }
+        };
+
+        let category = &mut self.external[index];
+        category.modules.clear();
+        if !commands.is_empty() {
+            category.modules.insert(name.to_string(), commands);
+        }
+        category.is_visible = !category.modules.is_empty();
+    }
+
+    /// Leaves one entry of every command registered by several categories.
+    /// Documented commands are not registered by anything, so they are never duplicates.
+    pub fn merge_duplicates(&mut self, mode: DuplicateMode, priority: &[String]) {
+        self.duplicates.clear();
+        if mode == DuplicateMode::Separate {
+            return;
+        }
+
+        // In the display order.
+        let mut owners: HashMap<String, Vec<CategoryKey>> = HashMap::new();
+        for &key in &self.order {
+            let category = &self[&key];
+            if !category.is_visible || !category.is_registered {
+                continue;
+            }
+            for commands in category.modules.values() {
+                for cmd in commands.keys() {
+                    let keys = owners.entry(cmd.clone()).or_default();
+                    if !keys.contains(&key) {
+                        keys.push(key);
+                    }
+                }
+            }
+        }
+
+        for (cmd, mut keys) in owners.into_iter().filter(|(_, keys)| keys.len() > 1) {
+            if mode == DuplicateMode::Prefer {
+                // Stable, so the display order decides between equal ones.
+                keys.sort_by_key(|key| {
+                    priority
+                        .iter()
+                        .position(|p| *p == self[key].name)
+                        .unwrap_or(priority.len())
+                });
+            }
+            let (_, others) = keys.split_first().unwrap();
+
+            let mut other_names = Vec::with_capacity(others.len());
+            for &key in others {
+                let category = self.category_mut(key);
+                for commands in category.modules.values_mut() {
+                    commands.remove(&cmd);
+                }
+                category.modules.retain(|_, commands| !commands.is_empty());
+                category.is_visible = !category.modules.is_empty();
+                other_names.push(category.name.clone());
+            }
+
+            if mode == DuplicateMode::Merge {
+                self.duplicates.insert(cmd, other_names);
+            }
+        }
+    }
+
+    /// Removes modules from every category, they are not shown then.
+    pub fn remove_modules(&mut self, is_removed: impl Fn(&str) -> bool) {
+        for category in self.iter_mut() {
+            category.modules.retain(|module, _| !is_removed(module));
+            category.is_visible = category.is_visible && !category.modules.is_empty();
+        }
+    }
+
+    /// Copy with the commands claimed by custom categories moved out of their source categories.
+    /// Commands are matched before modules, the first matching category wins.
+    /// Documented commands are not registered, so they stay where they are.
+    pub fn regroup(&self, custom: &[CustomCategory]) -> Categories {
+        let mut grouped = self.clone();
+        grouped.custom = custom
+            .iter()
+            .map(|c| Category::new(c.name.clone()))
+            .collect();
+
+        let target = |module: &str, cmd: &str| {
+            custom
+                .iter()
+                .position(|c| c.commands.iter().any(|c| c == cmd))
+                .or_else(|| {
+                    custom
+                        .iter()
+                        .position(|c| c.modules.iter().any(|m| m == module))
+                })
+        };
+
+        let sources = [
+            &mut grouped.samp,
+            &mut grouped.sf,
+            &mut grouped.cleo,
+            &mut grouped.lua,
+        ];
+        for source in sources.into_iter().chain(grouped.external.iter_mut()) {
+            let source_name = &source.name;
+            for (module, commands) in source.modules.iter_mut() {
+                commands.retain(|cmd, description| match target(module, cmd) {
+                    Some(i) => {
+                        let category = &mut grouped.custom[i];
+                        category
+                            .modules
+                            .entry(module.clone())
+                            .or_default()
+                            .insert(cmd.clone(), description.clone());
+                        category
+                            .sources
+                            .entry(module.clone())
+                            .or_insert_with(|| source_name.clone());
+                        false
+                    }
+                    None => true,
+                });
+            }
+            source.modules.retain(|_, commands| !commands.is_empty());
+            source.is_visible = source.is_visible && !source.modules.is_empty();
+        }
+
+        for category in grouped.custom.iter_mut() {
+            category.is_visible = !category.modules.is_empty();
+        }
+
+        // Custom categories go first, the user has created them for a reason.
+        grouped.order = (0..custom.len())
+            .map(CategoryKey::Custom)
+            .chain(self.order.iter().copied())
+            .collect();
+
+        grouped
+    }
+
+    /// Commands of all visible categories in one list, sorted by name.
+    pub fn entries(&self) -> Vec<CommandEntry> {
+        let mut entries = Vec::new();
+        for category in self.iter().filter(|c| c.is_visible) {
+            for (module, commands) in &category.modules {
+                for (cmd, description) in commands {
+                    entries.push(CommandEntry {
+                        category: &category.name,
+                        source: category.source_of(module),
+                        is_registered: category.is_registered,
+                        module,
+                        command: cmd,
+                        description,
+                    });
+                }
+            }
+        }
+        entries.sort_by(|a, b| a.command.cmp(b.command).then(a.category.cmp(b.category)));
+        entries
+    }
+
+    /// Whether the command is registered by a visible category.
+    pub fn contains(&self, command: &str) -> bool {
+        self.iter()
+            .filter(|c| c.is_visible && c.is_registered)
+            .any(|c| c.modules.values().any(|cmds| cmds.contains_key(command)))
+    }
+
+    /// Commands registered by more than one module, sorted by name.
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let mut owners: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+        for category in self.iter().filter(|c| c.is_registered) {
+            for (module, commands) in &category.modules {
+                for cmd in commands.keys() {
+                    owners.entry(cmd).or_default().push(format!(
+                        "{}: {}",
+                        category.source_of(module),
+                        module
+                    ));
+                }
+            }
+        }
+
+        owners
+            .into_iter()
+            .filter(|(_, owners)| owners.len() > 1)
+            .map(|(cmd, mut owners)| {
+                owners.sort();
+                Conflict {
+                    command: cmd.to_string(),
+                    owners,
+                }
+            })
+            .collect()
+    }
+
+    pub fn apply_descriptions(&mut self, descriptions: &HashMap<String, String>) {
+        for category in self.iter_mut() {
+            for commands in category.modules.values_mut() {
+                for (cmd, description) in commands.iter_mut() {
+                    *description = descriptions.get(cmd).cloned().unwrap_or_default();
+                }
+            }
+        }
+    }
+}
+
+/// A command that several modules have registered, only one of them handles it.
+pub struct Conflict {
+    pub command: String,
+    /// "Source: module" of each.
+    pub owners: Vec<String>,
+}
+
+pub struct CommandEntry<'a> {
+    pub category: &'a str,
+    /// Differs from the category for custom ones.
+    pub source: &'a str,
+    pub is_registered: bool,
+    pub module: &'a str,
+    pub command: &'a str,
+    pub description: &'a str,
 }
 
 impl std::ops::Index<&CategoryKey> for Categories {
@@ -76,6 +382,9 @@ impl std::ops::Index<&CategoryKey> for Categories {
             CategoryKey::SfPlugin => &self.sf,
             CategoryKey::Cleo => &self.cleo,
             CategoryKey::Lua => &self.lua,
+            CategoryKey::Documented => &self.documented,
+            CategoryKey::Custom(i) => &self.custom[*i],
+            CategoryKey::External(i) => &self.external[*i],
         }
     }
 }
@@ -105,3 +414,373 @@ pub fn cmd_with_prefix(command: &str) -> String {
     str.push_str(command);
     str
 }
+
+/// Name of the command as it is shown, the prefix is kept. A name is never cleaned up to nothing.
+pub fn normalize_command_name(command: &str, rules: &NameNormalization) -> String {
+    let (prefix, mut name) = match command.strip_prefix(CMD_PREFIX) {
+        Some(name) => (CMD_PREFIX, name),
+        None => ("", command),
+    };
+    if !rules.enabled {
+        return command.to_string();
+    }
+
+    if let Some(stripped) = rules
+        .suffixes
+        .iter()
+        .filter(|suffix| !suffix.is_empty())
+        .find_map(|suffix| name.strip_suffix(suffix.as_str()))
+    {
+        if !stripped.is_empty() {
+            name = stripped;
+        }
+    }
+    if rules.version_suffix {
+        name = strip_version_suffix(name);
+    }
+    let trimmed = name.trim_matches(|c| rules.trim_chars.contains(c));
+    if !trimmed.is_empty() {
+        name = trimmed;
+    }
+
+    format!("{}{}", prefix, name)
+}
+
+// Only after a separator, so "/car2" stays as it is.
+fn strip_version_suffix(name: &str) -> &str {
+    let rest = name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    if !name[rest.len()..].contains(|c: char| c.is_ascii_digit()) {
+        return name;
+    }
+    let rest = rest.strip_suffix(['v', 'V']).unwrap_or(rest);
+    match rest.strip_suffix(['_', '-']) {
+        Some(stem) if !stem.is_empty() => stem,
+        _ => name,
+    }
+}
+
+/// The typed command without its arguments, e.g. "/give" for "/give 5".
+pub fn command_token(input: &str) -> &str {
+    input.split(char::is_whitespace).next().unwrap_or(input)
+}
+
+/// Case-insensitive `starts_with`, also for non-ASCII letters.
+pub fn starts_with_ignore_case(text: &str, prefix: &str) -> bool {
+    let mut chars = text.chars();
+    prefix.chars().all(|p| {
+        chars
+            .next()
+            .is_some_and(|c| c.to_lowercase().eq(p.to_lowercase()))
+    })
+}
+
+/// Longest beginning shared by all commands, None if there are none.
+pub fn longest_common_prefix(commands: &[String]) -> Option<&str> {
+    let (first, rest) = commands.split_first()?;
+    let mut len = first.len();
+    for cmd in rest {
+        len = first
+            .char_indices()
+            .zip(cmd.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(len.min(cmd.len()), |((i, _), _)| i.min(len));
+    }
+    Some(&first[..len])
+}
+
+/// What Tab does with the typed command.
+#[derive(Debug, PartialEq)]
+pub enum Completion<'a> {
+    /// Only this command matches, it is inserted.
+    Command(&'a str),
+    /// The typed text is extended to the common beginning of the matching commands.
+    Prefix(&'a str),
+}
+
+/// Nothing while arguments are typed, the command they belong to must not be replaced.
+/// The typed case may differ, the registered one replaces it.
+pub fn complete<'a>(text: &str, commands: &'a [String]) -> Option<Completion<'a>> {
+    if command_token(text) != text {
+        return None;
+    }
+    let first = commands.first()?;
+    if commands.iter().all(|cmd| cmd == first) {
+        return Some(Completion::Command(first));
+    }
+
+    let prefix = longest_common_prefix(commands)?;
+    (prefix.chars().count() > text.chars().count() && starts_with_ignore_case(prefix, text))
+        .then_some(Completion::Prefix(prefix))
+}
+
+/// Finds words starting with the prefix in a chat message, e.g. in the output of /help.
+pub fn extract_commands(text: &str) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut previous = ' ';
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        // Not a part of a word, a path or an URL.
+        let is_start =
+            c == '/' && !(previous.is_alphanumeric() || previous == '/' || previous == ':');
+        previous = c;
+        if !is_start {
+            continue;
+        }
+
+        let mut end = i + c.len_utf8();
+        while let Some(&(j, next)) = chars.peek() {
+            if !(next.is_alphanumeric() || next == '_') {
+                break;
+            }
+            end = j + next.len_utf8();
+            previous = next;
+            chars.next();
+        }
+
+        if end > i + c.len_utf8() {
+            commands.push(text[i..end].to_string());
+        }
+    }
+
+    commands
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MatchMode {
+    #[default]
+    Prefix,
+    CaseInsensitivePrefix,
+    Fuzzy,
+}
+
+/// Higher is better. Only meaningful for comparing matches of the same query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MatchScore(u32);
+
+impl MatchScore {
+    pub const ANY: MatchScore = MatchScore(0);
+    pub const EXACT: MatchScore = MatchScore(u32::MAX);
+    const PREFIX: u32 = 2_000_000;
+    const FUZZY: u32 = 1_000_000;
+}
+
+/// Checks whether `command` matches the typed `query`.
+/// An empty query matches everything, so that the full list is shown.
+pub fn match_command(query: &str, command: &str, mode: MatchMode) -> Option<MatchScore> {
+    if query.is_empty() {
+        return Some(MatchScore::ANY);
+    }
+
+    let eq = |a: char, b: char| match mode {
+        MatchMode::Prefix => a == b,
+        MatchMode::CaseInsensitivePrefix | MatchMode::Fuzzy => {
+            a.to_lowercase().eq(b.to_lowercase())
+        }
+    };
+
+    let mut cmd_chars = command.chars();
+    if query
+        .chars()
+        .all(|q| cmd_chars.next().is_some_and(|c| eq(q, c)))
+    {
+        let rest = cmd_chars.count() as u32;
+        return Some(if rest == 0 {
+            MatchScore::EXACT
+        } else {
+            // Shorter commands are closer to what has been typed.
+            MatchScore(MatchScore::PREFIX - rest.min(MatchScore::PREFIX - MatchScore::FUZZY))
+        });
+    }
+
+    if mode != MatchMode::Fuzzy {
+        return None;
+    }
+
+    // Every character of the query must appear in the command in the same order.
+    let mut gaps = 0u32;
+    let mut cmd_chars = command.chars();
+    for q in query.chars() {
+        loop {
+            match cmd_chars.next() {
+                Some(c) if eq(q, c) => break,
+                Some(_) => gaps += 1,
+                None => return None,
+            }
+        }
+    }
+
+    Some(MatchScore(MatchScore::FUZZY - gaps.min(MatchScore::FUZZY)))
+}
+
+/// Builds a newline-separated list of commands that match the query, sorted by module and name.
+pub fn format_command_list(
+    categories: &Categories,
+    query: &str,
+    mode: MatchMode,
+    with_headers: bool,
+) -> String {
+    let mut text = String::new();
+
+    for category in categories.iter().filter(|c| c.is_visible) {
+        let mut modules: Vec<_> = category.modules.iter().collect();
+        modules.sort_by(|a, b| a.0.cmp(b.0));
+
+        for (module, commands) in modules {
+            let mut commands: Vec<_> = commands
+                .keys()
+                .filter(|cmd| match_command(query, cmd, mode).is_some())
+                .collect();
+            if commands.is_empty() {
+                continue;
+            }
+            commands.sort();
+
+            if with_headers {
+                text.push_str(&format!("[{}] {}\n", category.name, module));
+            }
+            for cmd in commands {
+                text.push_str(cmd);
+                text.push('\n');
+            }
+        }
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(commands: &[&str]) -> Vec<String> {
+        commands.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn common_prefix_of_commands() {
+        assert_eq!(longest_common_prefix(&[]), None);
+        assert_eq!(longest_common_prefix(&strings(&["/help"])), Some("/help"));
+        assert_eq!(
+            longest_common_prefix(&strings(&["/help", "/hello", "/helpme"])),
+            Some("/hel")
+        );
+        assert_eq!(longest_common_prefix(&strings(&["/a", "/b"])), Some("/"));
+        assert_eq!(
+            longest_common_prefix(&strings(&["/привет", "/прием"])),
+            Some("/при")
+        );
+    }
+
+    #[test]
+    fn completion_keeps_the_registered_case() {
+        // Matched case-insensitively, so the typed case differs from the registered one.
+        let typed = "/He";
+        let commands = strings(&["/help", "/hello"]);
+        assert!(commands.iter().all(|cmd| match_command(
+            typed,
+            cmd,
+            MatchMode::CaseInsensitivePrefix
+        )
+        .is_some()));
+
+        let prefix = longest_common_prefix(&commands).unwrap();
+        assert_eq!(prefix, "/hel");
+        assert!(starts_with_ignore_case(prefix, typed));
+        assert!(!prefix.starts_with(typed));
+    }
+
+    #[test]
+    fn completion_without_arguments() {
+        let commands = strings(&["/give", "/gift"]);
+        assert_eq!(complete("/g", &commands), Some(Completion::Prefix("/gi")));
+        // Nothing to extend.
+        assert_eq!(complete("/gi", &commands), None);
+        assert_eq!(complete("/gi", &[]), None);
+
+        let single = strings(&["/give", "/give"]);
+        assert_eq!(complete("/g", &single), Some(Completion::Command("/give")));
+        assert_eq!(complete("/G", &commands), Some(Completion::Prefix("/gi")));
+    }
+
+    #[test]
+    fn completion_with_arguments() {
+        // The list still shows the command, but the arguments must not be lost.
+        let commands = strings(&["/give"]);
+        assert_eq!(command_token("/give 5"), "/give");
+        assert!(match_command(command_token("/give 5"), "/give", MatchMode::Prefix).is_some());
+        assert_eq!(complete("/give 5", &commands), None);
+        assert_eq!(complete("/give ", &commands), None);
+        assert_eq!(
+            complete("/gi", &commands),
+            Some(Completion::Command("/give"))
+        );
+    }
+
+    #[test]
+    fn starts_with_ignoring_case() {
+        assert!(starts_with_ignore_case("/Help", "/hE"));
+        assert!(starts_with_ignore_case("/ПОМОЩЬ", "/пом"));
+        assert!(!starts_with_ignore_case("/he", "/help"));
+    }
+
+    #[test]
+    fn empty_query_matches_everything() {
+        for mode in [
+            MatchMode::Prefix,
+            MatchMode::CaseInsensitivePrefix,
+            MatchMode::Fuzzy,
+        ] {
+            assert_eq!(match_command("", "/help", mode), Some(MatchScore::ANY));
+        }
+    }
+
+    #[test]
+    fn prefix_mode_is_case_sensitive() {
+        let mode = MatchMode::Prefix;
+        assert_eq!(
+            match_command("/help", "/help", mode),
+            Some(MatchScore::EXACT)
+        );
+        assert!(match_command("/he", "/help", mode).is_some());
+        assert!(match_command("/HE", "/help", mode).is_none());
+        assert!(match_command("/elp", "/help", mode).is_none());
+        assert!(match_command("/helpme", "/help", mode).is_none());
+    }
+
+    #[test]
+    fn case_insensitive_prefix() {
+        let mode = MatchMode::CaseInsensitivePrefix;
+        assert_eq!(
+            match_command("/HELP", "/help", mode),
+            Some(MatchScore::EXACT)
+        );
+        assert!(match_command("/He", "/help", mode).is_some());
+        assert!(match_command("/ПОМ", "/помощь", mode).is_some());
+        // Only the beginning, not a substring.
+        assert!(match_command("/elp", "/help", mode).is_none());
+    }
+
+    #[test]
+    fn fuzzy_matches_in_order() {
+        let mode = MatchMode::Fuzzy;
+        assert!(match_command("/elp", "/help", mode).is_some());
+        assert!(match_command("/hp", "/help", mode).is_some());
+        assert!(match_command("/HP", "/help", mode).is_some());
+        assert!(match_command("/ph", "/help", mode).is_none());
+    }
+
+    #[test]
+    fn closer_matches_score_higher() {
+        let mode = MatchMode::Fuzzy;
+        let exact = match_command("/help", "/help", mode);
+        let prefix = match_command("/he", "/help", mode);
+        let longer_prefix = match_command("/he", "/helpme", mode);
+        let fuzzy = match_command("/hp", "/help", mode);
+        let gappy = match_command("/hp", "/hello_op", mode);
+        assert!(exact > prefix);
+        assert!(prefix > longer_prefix);
+        assert!(longer_prefix > fuzzy);
+        assert!(fuzzy > gappy);
+    }
+}
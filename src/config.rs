@@ -0,0 +1,708 @@
+/*****************************************************************************
+ *
+ *  PROJECT:        samp-cmd-helper
+ *  LICENSE:        See LICENSE in the top level directory
+ *  FILE:           config.rs
+ *  DESCRIPTION:    User settings
+ *  COPYRIGHT:      (c) 2024 RINWARES <rinwares.com>
+ *  AUTHOR:         Rinat Namazov <rinat.namazov@rinwares.com>
+ *
+ *****************************************************************************/
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::cmd_storage::{cmd_with_prefix, CategoryKey, MatchMode, CMD_PREFIX};
+use crate::errors::Error;
+use crate::samp::MAX_RECALL_HISTORY;
+
+pub const DATA_DIRECTORY: &str = "samp-cmd-helper";
+pub const CONFIG_FILE: &str = "config.json";
+pub const DESCRIPTIONS_DIRECTORY: &str = "descriptions";
+pub const FONTS_DIRECTORY: &str = "fonts";
+pub const DEFAULT_DESCRIPTIONS: &str = "default";
+pub const NOTES_FILE: &str = "notes.json";
+
+/// Command with prefix -> description.
+pub type Descriptions = HashMap<String, String>;
+/// Command with prefix -> note written by the user in game.
+pub type Notes = BTreeMap<String, String>;
+
+/// Command with prefix -> names of its parameters.
+pub type Templates = HashMap<String, Vec<String>>;
+
+// A description alone or together with a template such as "/give {playerid} {amount}".
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DescriptionEntry {
+    Text(String),
+    Detailed {
+        #[serde(default)]
+        description: String,
+        #[serde(default)]
+        template: String,
+    },
+}
+
+// The command itself may be omitted in the template.
+fn parse_template(template: &str) -> Vec<String> {
+    let mut params = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start + 1..].find('}') else {
+            break;
+        };
+        let name = rest[start + 1..start + 1 + len].trim();
+        if !name.is_empty() {
+            params.push(name.to_string());
+        }
+        rest = &rest[start + 1 + len + 1..];
+    }
+    params
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Anchor {
+    #[default]
+    FollowChat,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Where the list of commands is shown.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Dock {
+    /// A window at the anchor.
+    #[default]
+    Floating,
+    /// A panel along the edge of the screen.
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ViewMode {
+    /// Column per category.
+    #[default]
+    Categories,
+    /// All commands in one list.
+    Unified,
+    /// All commands in one list, under headers of their first letter.
+    Alphabetical,
+}
+
+/// How commands that don't match the typed text are shown.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum UnmatchedMode {
+    /// Dimmed, after the matching ones.
+    #[default]
+    Dimmed,
+    /// Dimmed, below a separator.
+    Separated,
+    Hidden,
+}
+
+/// How a command registered by several sources is shown.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DuplicateMode {
+    /// In every category that has it.
+    #[default]
+    Separate,
+    /// Once, in the first category, tagged with the other ones.
+    Merge,
+    /// Only in the category that comes first in `duplicate_priority`.
+    Prefer,
+}
+
+/// Category of commands chosen by the user rather than by where they come from.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CustomCategory {
+    pub name: String,
+    /// Modules moved here with all of their commands, e.g. "admin.lua".
+    pub modules: Vec<String>,
+    /// Commands with prefix moved here, e.g. "/ban".
+    pub commands: Vec<String>,
+}
+
+/// How commands of a category are put into the chat.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InsertMode {
+    /// Some scripts handle their commands without the prefix.
+    pub prefix: bool,
+    /// Send the command right away instead of leaving it for arguments.
+    pub send: bool,
+}
+
+impl Default for InsertMode {
+    fn default() -> Self {
+        Self {
+            prefix: true,
+            send: false,
+        }
+    }
+}
+
+// Unknown categories are dropped rather than failing the whole config.
+fn known_categories<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<CategoryKey, InsertMode>, D::Error> {
+    let modes = HashMap::<String, InsertMode>::deserialize(deserializer)?;
+    Ok(modes
+        .into_iter()
+        .filter_map(|(key, mode)| {
+            let key = serde_json::from_value(serde_json::Value::String(key)).ok()?;
+            Some((key, mode))
+        })
+        .collect())
+}
+
+/// Cleans up how command names are shown, what is inserted stays as registered.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NameNormalization {
+    /// Off by default, names are shown as registered.
+    pub enabled: bool,
+    /// Characters removed from both ends of a name, e.g. "__cmd__".
+    pub trim_chars: String,
+    /// Removed from the end of a name, the first one that matches.
+    pub suffixes: Vec<String>,
+    /// Removes versions like "_v2" or "-1.3" from the end of a name.
+    pub version_suffix: bool,
+}
+
+impl Default for NameNormalization {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            trim_chars: "_".to_string(),
+            suffixes: Vec::new(),
+            version_suffix: true,
+        }
+    }
+}
+
+/// Names of egui keys, e.g. "Enter" or "F2". Empty to not bind.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub toggle_overlay: String,
+    /// Pressed with Ctrl, like `select_previous`.
+    pub select_next: String,
+    pub select_previous: String,
+    /// Inserts the selected command, with Ctrl keeps the list open.
+    pub insert: String,
+    pub dismiss: String,
+    /// Extends the typed command to the common beginning of the matching ones.
+    pub complete: String,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            toggle_overlay: String::new(),
+            select_next: "ArrowDown".to_string(),
+            select_previous: "ArrowUp".to_string(),
+            insert: "Enter".to_string(),
+            dismiss: "Escape".to_string(),
+            complete: "Tab".to_string(),
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&'static str, &mut String)> {
+        [
+            ("Toggle overlay", &mut self.toggle_overlay),
+            ("Select next (Ctrl+)", &mut self.select_next),
+            ("Select previous (Ctrl+)", &mut self.select_previous),
+            ("Insert selected", &mut self.insert),
+            ("Dismiss list", &mut self.dismiss),
+            ("Complete common prefix", &mut self.complete),
+        ]
+        .into_iter()
+    }
+}
+
+pub const MIN_COLUMN_WIDTH: f32 = 50.0;
+pub const MAX_COLUMN_WIDTH: f32 = 600.0;
+pub const MIN_ITEM_SPACING: f32 = 0.0;
+pub const MAX_ITEM_SPACING: f32 = 16.0;
+pub const MIN_UI_SCALE: f32 = 0.5;
+pub const MAX_UI_SCALE: f32 = 3.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub anchor: Anchor,
+    /// Ignores the anchors of the commands unless floating.
+    pub dock: Dock,
+    pub anchor_margin: f32,
+    /// Following the chat, show the windows above the input when there is more space there.
+    pub flip_above_chat: bool,
+    pub recall_anchor: Anchor,
+    /// Added to the position of the recalls window.
+    pub recall_offset: [f32; 2],
+    pub match_mode: MatchMode,
+    pub unmatched_mode: UnmatchedMode,
+    /// Characters typed after the prefix before the commands are shown, 0 to show them on the prefix.
+    pub min_query_length: usize,
+    pub view_mode: ViewMode,
+    /// Categories that show all their commands regardless of what is typed.
+    pub unfiltered_categories: BTreeSet<String>,
+    /// Tag commands with their category in the unified list.
+    pub show_source: bool,
+    pub copy_with_headers: bool,
+    pub show_server_address: bool,
+    /// Columns are as wide as the chat allows, but not narrower than this.
+    pub min_column_width: f32,
+    /// Vertical space between commands.
+    pub item_spacing: f32,
+    /// Text size multiplier, for high resolution screens.
+    pub ui_scale: f32,
+    /// Separates the windows from a bright game scene.
+    pub window_shadow: bool,
+    pub window_border: bool,
+    /// RGBA, not premultiplied.
+    pub window_border_color: [u8; 4],
+    /// Fallback fonts from the fonts directory, in order of priority.
+    pub fonts: Vec<String>,
+    /// Chat command that opens the settings instead of being sent, empty to disable.
+    pub settings_command: String,
+    /// Serve the command list to external tools through a named pipe.
+    pub ipc_pipe: bool,
+    /// Take descriptions from chat messages of the server that match the format.
+    pub server_descriptions: bool,
+    /// Must contain {command} followed by {description}, e.g. "{command} - {description}".
+    pub server_description_format: String,
+    /// Suggest commands mentioned in recent chat messages, e.g. in the output of /help.
+    pub chat_suggestions: bool,
+    /// Show commands from descriptions that are not registered.
+    pub show_documented: bool,
+    pub insert_documented: bool,
+    /// Commands with prefix the user doesn't want to see.
+    pub hidden_commands: BTreeSet<String>,
+    /// Commands with prefix that ask before being inserted, e.g. "/kill".
+    pub confirm_commands: BTreeSet<String>,
+    pub show_hidden: bool,
+    /// Display "/command" rather than "command".
+    pub show_prefix: bool,
+    pub name_normalization: NameNormalization,
+    /// Show descriptions under the commands instead of in tooltips.
+    pub inline_descriptions: bool,
+    /// Display "plugin" rather than "plugin.dll" in module headers.
+    pub strip_module_extensions: bool,
+    /// Latest chat recalls shown, up to 10.
+    pub max_recalls: usize,
+    /// Commands shown per module before the rest are collapsed, 0 for no limit.
+    pub max_commands_per_module: usize,
+    /// Number the first matching commands to insert them with Alt+1..9.
+    pub quick_insert: bool,
+    /// Briefly highlight a command after inserting it.
+    pub insert_feedback: bool,
+    /// Hide the overlay while the game window is not active, e.g. after Alt+Tab.
+    pub hide_on_focus_loss: bool,
+    /// Hide the overlay after this many seconds without typing, 0 to never hide.
+    pub auto_hide_seconds: f32,
+    /// Commands not claimed by any of them stay in their source category.
+    pub custom_categories: Vec<CustomCategory>,
+    pub duplicate_mode: DuplicateMode,
+    /// Built-in category that has registered the command -> how its commands are inserted,
+    /// the rest use the default. Custom categories don't change it.
+    #[serde(deserialize_with = "known_categories")]
+    pub insert_modes: HashMap<CategoryKey, InsertMode>,
+    /// Category names, categories that are not listed come after them.
+    pub duplicate_priority: Vec<String>,
+    /// Modules whose commands are not shown, e.g. "admin.asi". Compared case-insensitively.
+    pub ignored_modules: BTreeSet<String>,
+    pub keys: KeyBindings,
+    /// Debug, show the handler address of SA-MP commands when hovering over them.
+    pub show_handler_addresses: bool,
+    /// Show the commands even when the chat is closed, as a reference.
+    pub pinned: bool,
+    pub pinned_anchor: Anchor,
+    /// Code page of SA-MP strings such as command names and the chat input, e.g. 1251. 0 for the system one.
+    pub code_page: u32,
+    /// Only new users see the tutorial, configs written before it existed are not new.
+    #[serde(default = "tutorial_shown_in_existing_config")]
+    pub tutorial_shown: bool,
+}
+
+fn tutorial_shown_in_existing_config() -> bool {
+    true
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            anchor: Anchor::FollowChat,
+            dock: Dock::Floating,
+            anchor_margin: 10.0,
+            flip_above_chat: true,
+            recall_anchor: Anchor::FollowChat,
+            recall_offset: [0.0, 0.0],
+            match_mode: MatchMode::Prefix,
+            unmatched_mode: UnmatchedMode::Dimmed,
+            min_query_length: 0,
+            view_mode: ViewMode::Categories,
+            show_source: true,
+            copy_with_headers: false,
+            show_server_address: false,
+            min_column_width: 100.0,
+            item_spacing: 3.0,
+            fonts: vec![
+                "latin.ttf".to_string(),
+                "cyrillic.ttf".to_string(),
+                "cjk.ttf".to_string(),
+            ],
+            settings_command: "/cmdhelper".to_string(),
+            ipc_pipe: false,
+            server_descriptions: false,
+            server_description_format: "{command} - {description}".to_string(),
+            chat_suggestions: false,
+            show_documented: true,
+            insert_documented: false,
+            hidden_commands: BTreeSet::new(),
+            confirm_commands: BTreeSet::new(),
+            show_hidden: false,
+            show_prefix: true,
+            name_normalization: NameNormalization::default(),
+            inline_descriptions: false,
+            ui_scale: 1.0,
+            window_shadow: false,
+            window_border: false,
+            window_border_color: [255, 255, 255, 60],
+            unfiltered_categories: BTreeSet::new(),
+            quick_insert: false,
+            insert_feedback: true,
+            hide_on_focus_loss: true,
+            strip_module_extensions: false,
+            max_commands_per_module: 0,
+            max_recalls: MAX_RECALL_HISTORY,
+            auto_hide_seconds: 0.0,
+            custom_categories: Vec::new(),
+            ignored_modules: BTreeSet::new(),
+            duplicate_mode: DuplicateMode::Separate,
+            insert_modes: HashMap::new(),
+            duplicate_priority: vec![
+                "SA-MP".to_string(),
+                "SF".to_string(),
+                "CLEO".to_string(),
+                "Lua".to_string(),
+            ],
+            keys: KeyBindings::default(),
+            show_handler_addresses: false,
+            tutorial_shown: false,
+            code_page: 0,
+            pinned: false,
+            pinned_anchor: Anchor::TopRight,
+        }
+    }
+}
+
+impl Config {
+    /// The file may contain anything.
+    pub fn ui_scale(&self) -> f32 {
+        self.ui_scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE)
+    }
+
+    pub fn is_module_ignored(&self, module: &str) -> bool {
+        self.ignored_modules
+            .iter()
+            .any(|ignored| ignored.eq_ignore_ascii_case(module))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let data = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// None if there is no file, the user just has not changed anything yet.
+    /// A file that can't be parsed is an error, it must not be replaced with the defaults.
+    pub fn load_if_exists(path: &Path) -> Result<Option<Self>, Error> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        Self::load(path).map(Some)
+    }
+}
+
+pub fn config_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(CONFIG_FILE)
+}
+
+fn load_descriptions_file(path: &Path) -> Result<(Descriptions, Templates), Error> {
+    let data = fs::read_to_string(path)?;
+    let entries: HashMap<String, DescriptionEntry> = serde_json::from_str(&data)?;
+
+    let mut descriptions = Descriptions::new();
+    let mut templates = Templates::new();
+    for (cmd, entry) in entries {
+        // Allow writing commands without the prefix.
+        let cmd = if cmd.starts_with(CMD_PREFIX) {
+            cmd
+        } else {
+            cmd_with_prefix(&cmd)
+        };
+
+        match entry {
+            DescriptionEntry::Text(description) => {
+                descriptions.insert(cmd, description);
+            }
+            DescriptionEntry::Detailed {
+                description,
+                template,
+            } => {
+                let params = parse_template(&template);
+                if !params.is_empty() {
+                    templates.insert(cmd.clone(), params);
+                }
+                if !description.is_empty() {
+                    descriptions.insert(cmd, description);
+                }
+            }
+        }
+    }
+
+    Ok((descriptions, templates))
+}
+
+// "127.0.0.1:7777" -> "127.0.0.1_7777", since ':' is not allowed in file names.
+fn server_file_name(server: &str) -> String {
+    server
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn descriptions_paths(data_dir: &Path, server: Option<&str>) -> Vec<PathBuf> {
+    let dir = data_dir.join(DESCRIPTIONS_DIRECTORY);
+
+    let mut paths = Vec::with_capacity(2);
+    if let Some(server) = server {
+        paths.push(dir.join(format!("{}.json", server_file_name(server))));
+    }
+    paths.push(dir.join(format!("{}.json", DEFAULT_DESCRIPTIONS)));
+    paths
+}
+
+// Kept apart from the descriptions, which may be replaced by a newer set.
+pub fn load_notes(data_dir: &Path) -> Notes {
+    let path = data_dir.join(NOTES_FILE);
+    if !path.exists() {
+        return Notes::new();
+    }
+
+    let result = fs::read_to_string(&path)
+        .map_err(Error::from)
+        .and_then(|data| serde_json::from_str(&data).map_err(Error::from));
+    match result {
+        Ok(notes) => notes,
+        Err(e) => {
+            eprintln!("config::load_notes: {}", e);
+            Notes::new()
+        }
+    }
+}
+
+pub fn save_notes(data_dir: &Path, notes: &Notes) -> Result<(), Error> {
+    fs::create_dir_all(data_dir)?;
+    fs::write(
+        data_dir.join(NOTES_FILE),
+        serde_json::to_string_pretty(notes)?,
+    )?;
+    Ok(())
+}
+
+/// Loads descriptions and templates for the server, falling back to the default ones.
+pub fn load_descriptions(data_dir: &Path, server: Option<&str>) -> (Descriptions, Templates) {
+    for path in descriptions_paths(data_dir, server) {
+        if !path.exists() {
+            continue;
+        }
+        match load_descriptions_file(&path) {
+            Ok(loaded) => return loaded,
+            Err(e) => eprintln!("config::load_descriptions: {}: {}", path.display(), e),
+        }
+    }
+
+    (Descriptions::new(), Templates::new())
+}
+
+// SA-MP color codes such as {FF0000}.
+fn strip_color_codes(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        let code = rest[start + 1..].get(..7);
+        if let Some(code) =
+            code.filter(|c| c.ends_with('}') && c[..6].chars().all(|c| c.is_ascii_hexdigit()))
+        {
+            result.push_str(&rest[..start]);
+            rest = &rest[start + 1 + code.len()..];
+        } else {
+            result.push_str(&rest[..=start]);
+            rest = &rest[start + 1..];
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Extracts a command and its description from a chat message announced by the server.
+pub fn parse_announced_description(format: &str, message: &str) -> Option<(String, String)> {
+    const COMMAND: &str = "{command}";
+    const DESCRIPTION: &str = "{description}";
+
+    let command_pos = format.find(COMMAND)?;
+    let description_pos = format.find(DESCRIPTION)?;
+    if description_pos < command_pos + COMMAND.len() {
+        return None;
+    }
+
+    let prefix = &format[..command_pos];
+    let separator = &format[command_pos + COMMAND.len()..description_pos];
+    let suffix = &format[description_pos + DESCRIPTION.len()..];
+    // Without a separator it is impossible to tell where the command ends.
+    if separator.is_empty() {
+        return None;
+    }
+
+    let message = strip_color_codes(message);
+    let rest = message.trim().strip_prefix(prefix)?.strip_suffix(suffix)?;
+    let (command, description) = rest.split_once(separator)?;
+
+    let command = command.trim();
+    let description = description.trim();
+    if command.is_empty() || command.contains(char::is_whitespace) || description.is_empty() {
+        return None;
+    }
+
+    let command = if command.starts_with(CMD_PREFIX) {
+        command.to_string()
+    } else {
+        cmd_with_prefix(command)
+    };
+    Some((command, description.to_string()))
+}
+
+/// Remembers modification times of files to notice when the user edits them.
+#[derive(Default)]
+pub struct FileWatcher {
+    files: HashMap<PathBuf, Option<SystemTime>>,
+}
+
+impl FileWatcher {
+    fn modified(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    /// Watches the config and the descriptions that can be loaded for the server.
+    pub fn watch_config(&mut self, data_dir: &Path, server: Option<&str>) {
+        self.files.clear();
+        self.watch(config_path(data_dir));
+        for path in descriptions_paths(data_dir, server) {
+            self.watch(path);
+        }
+    }
+
+    pub fn watch(&mut self, path: PathBuf) {
+        let modified = Self::modified(&path);
+        self.files.insert(path, modified);
+    }
+
+    /// Creation and removal of a file are also changes.
+    pub fn changed(&mut self) -> bool {
+        let mut changed = false;
+        for (path, modified) in self.files.iter_mut() {
+            let current = Self::modified(path);
+            if current != *modified {
+                *modified = current;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_config(name: &str, data: Option<&str>) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "samp-cmd-helper-{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        if let Some(data) = data {
+            fs::write(&path, data).unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn malformed_config_is_an_error() {
+        let path = temp_config("malformed", Some("{ \"max_suggestions\": "));
+        assert!(Config::load_if_exists(&path).is_err());
+        // The file is left as the user wrote it.
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "{ \"max_suggestions\": "
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn missing_config_is_not_an_error() {
+        let path = temp_config("missing", None);
+        assert!(matches!(Config::load_if_exists(&path), Ok(None)));
+    }
+
+    #[test]
+    fn insert_modes_are_saved_by_category_key() {
+        let mut config = Config::default();
+        let mode = InsertMode {
+            prefix: false,
+            send: true,
+        };
+        config.insert_modes.insert(CategoryKey::Lua, mode);
+
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(json.contains(r#""insert_modes":{"Lua":"#));
+        let loaded: Config = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.insert_modes.get(&CategoryKey::Lua), Some(&mode));
+    }
+
+    #[test]
+    fn unknown_insert_mode_categories_are_dropped() {
+        let json = r#"{ "insert_modes": { "Cleo": { "prefix": false }, "My scripts": {} } }"#;
+        let config: Config = serde_json::from_str(json).unwrap();
+        assert_eq!(config.insert_modes.len(), 1);
+        assert!(!config.insert_modes[&CategoryKey::Cleo].prefix);
+    }
+}
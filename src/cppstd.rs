@@ -9,7 +9,69 @@
  *
  *****************************************************************************/
 
-use std::ffi::{c_char, CStr};
+use std::ffi::{c_char, c_void};
+
+use crate::utils;
+
+/// `free` of the CRT the memory was allocated with.
+pub type CrtFree = unsafe extern "C" fn(*mut c_void);
+
+/// DLLs of the MSVC runtime, whose `free` matches the `operator new` of MSVC std containers.
+/// Nothing else is trusted to free them, e.g. msvcrt.dll of MinGW.
+pub fn is_msvc_crt(dll: &str) -> bool {
+    let dll = dll.to_ascii_lowercase();
+    dll == "ucrtbase.dll"
+        || dll.starts_with("api-ms-win-crt-heap-")
+        || dll
+            .strip_prefix("msvcr")
+            .and_then(|rest| rest.strip_suffix(".dll"))
+            .is_some_and(|version| {
+                !version.is_empty() && version.bytes().all(|b| b.is_ascii_digit())
+            })
+}
+
+// MSVC std::allocator aligns blocks of 4096 bytes and more to 32 bytes
+// and stores the pointer returned by operator new right before the aligned one.
+const BIG_ALLOCATION_THRESHOLD: usize = 4096;
+const BIG_ALLOCATION_ALIGNMENT: usize = 32;
+// Bounds of the distance to the block returned by operator new, as `_Adjust_manually_vector_aligned`
+// checks them in release builds. Debug builds also put a sentinel before the back pointer.
+const MIN_BACK_SHIFT: usize = std::mem::size_of::<usize>();
+const MAX_BACK_SHIFT: usize = std::mem::size_of::<usize>() + BIG_ALLOCATION_ALIGNMENT - 1;
+
+// The block returned by operator new for a big allocation, None if the layout doesn't match.
+unsafe fn big_allocation_block(ptr: *mut c_void) -> Option<*mut c_void> {
+    let user = ptr as usize;
+    if user % BIG_ALLOCATION_ALIGNMENT != 0
+        || utils::readable_size(user - MIN_BACK_SHIFT) < MIN_BACK_SHIFT
+    {
+        return None;
+    }
+    let block = *(ptr as *const usize).sub(1);
+    let back_shift = user.wrapping_sub(block);
+    (MIN_BACK_SHIFT..=MAX_BACK_SHIFT)
+        .contains(&back_shift)
+        .then_some(block as *mut c_void)
+}
+
+// A buffer that doesn't look like MSVC allocated it is leaked on purpose,
+// freeing it with the wrong pointer would corrupt the heap of the game.
+unsafe fn deallocate(ptr: *mut c_void, bytes: usize, free: CrtFree) {
+    if ptr.is_null() {
+        return;
+    }
+    if bytes < BIG_ALLOCATION_THRESHOLD {
+        free(ptr);
+        return;
+    }
+    match big_allocation_block(ptr) {
+        Some(block) => free(block),
+        None => eprintln!(
+            "cppstd::deallocate: {:p}: unexpected block layout, leaked",
+            ptr
+        ),
+    }
+}
 
 #[repr(C)]
 pub struct StdVector<T> {
@@ -26,6 +88,15 @@ impl<T> StdVector<T> {
     pub fn capacity(&self) -> usize {
         (self.end as usize - self.first as usize) / std::mem::size_of::<T>()
     }
+
+    /// Frees the buffer of a vector returned by value from C++, the elements must be destroyed before.
+    pub unsafe fn free_buffer(&self, free: CrtFree) {
+        deallocate(
+            self.first as *mut c_void,
+            self.capacity() * std::mem::size_of::<T>(),
+            free,
+        );
+    }
 }
 
 impl<'a, T> IntoIterator for &'a StdVector<T> {
@@ -61,9 +132,12 @@ impl<'a, T> Iterator for StdVectorIterator<'a, T> {
     }
 }
 
+// Up to 15 characters and the nul are stored inside the string itself.
+const SSO_BUFFER_SIZE: usize = 16;
+
 #[repr(C)]
 union StdStringUnion {
-    buf: [u8; 16],
+    buf: [u8; SSO_BUFFER_SIZE],
     ptr: *const c_char,
 }
 
@@ -75,16 +149,161 @@ pub struct StdString {
 }
 
 impl StdString {
-    pub fn to_string(&self) -> String {
+    // MSVC decides by the capacity, not the size: a string that has been shortened keeps its buffer.
+    fn is_inline(&self) -> bool {
+        (self.capacity as usize) < SSO_BUFFER_SIZE
+    }
+
+    /// Frees the buffer of a string returned by value from C++, short strings have none.
+    pub unsafe fn free_buffer(&self, free: CrtFree) {
+        if !self.is_inline() {
+            deallocate(
+                self.str.ptr as *mut c_void,
+                self.capacity as usize + 1,
+                free,
+            );
+        }
+    }
+
+    /// The `size` bytes of the string, read by length rather than up to a nul.
+    pub fn as_bytes(&self) -> &[u8] {
         unsafe {
-            if self.size < 16 {
-                CStr::from_bytes_until_nul(&self.str.buf)
-                    .unwrap()
-                    .to_string_lossy()
-                    .to_string()
+            if self.is_inline() {
+                &self.str.buf[..(self.size as usize).min(SSO_BUFFER_SIZE - 1)]
+            } else if self.str.ptr.is_null() {
+                &[]
             } else {
-                CStr::from_ptr(self.str.ptr).to_string_lossy().to_string()
+                std::slice::from_raw_parts(self.str.ptr as *const u8, self.size as usize)
+            }
+        }
+    }
+
+    pub fn to_string(&self) -> String {
+        utils::decode_ansi(self.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Lays the string out as MSVC does. A long one gets a terminated buffer that is never freed.
+    fn std_string(bytes: &[u8], capacity: usize) -> StdString {
+        assert!(capacity >= bytes.len());
+        let str = if capacity < SSO_BUFFER_SIZE {
+            let mut buf = [0u8; SSO_BUFFER_SIZE];
+            buf[..bytes.len()].copy_from_slice(bytes);
+            StdStringUnion { buf }
+        } else {
+            let mut heap = vec![0u8; capacity + 1];
+            heap[..bytes.len()].copy_from_slice(bytes);
+            StdStringUnion {
+                ptr: Box::leak(heap.into_boxed_slice()).as_ptr() as *const c_char,
             }
+        };
+        StdString {
+            str,
+            size: bytes.len() as u32,
+            capacity: capacity as u32,
         }
     }
+
+    // The smallest capacity MSVC gives a string of this length.
+    fn std_string_of(text: &str) -> StdString {
+        std_string(text.as_bytes(), text.len().max(SSO_BUFFER_SIZE - 1))
+    }
+
+    #[test]
+    fn round_trip_at_the_inline_limit() {
+        let text = "a".repeat(15);
+        let s = std_string_of(&text);
+        assert!(s.is_inline());
+        assert_eq!(s.as_bytes(), text.as_bytes());
+        assert_eq!(s.to_string(), text);
+    }
+
+    #[test]
+    fn round_trip_on_the_heap() {
+        let text = "b".repeat(16);
+        let s = std_string_of(&text);
+        assert!(!s.is_inline());
+        assert_eq!(s.as_bytes(), text.as_bytes());
+        assert_eq!(s.to_string(), text);
+    }
+
+    #[test]
+    fn inline_is_decided_by_capacity() {
+        assert!(std_string_of(&"c".repeat(14)).is_inline());
+        assert!(std_string_of(&"c".repeat(15)).is_inline());
+        assert!(!std_string_of(&"c".repeat(16)).is_inline());
+        // Shortened, but still with its heap buffer.
+        let s = std_string(b"abc", 16);
+        assert!(!s.is_inline());
+        assert_eq!(s.as_bytes(), b"abc");
+    }
+
+    static FREED: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn record_free(ptr: *mut c_void) {
+        FREED.store(ptr as usize, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn only_heap_buffers_are_freed() {
+        for size in [14, 15] {
+            FREED.store(0, Ordering::SeqCst);
+            unsafe { std_string_of(&"d".repeat(size)).free_buffer(record_free) };
+            assert_eq!(FREED.load(Ordering::SeqCst), 0);
+        }
+
+        let s = std_string_of(&"d".repeat(16));
+        unsafe { s.free_buffer(record_free) };
+        assert_eq!(FREED.load(Ordering::SeqCst), unsafe { s.str.ptr } as usize);
+    }
+
+    #[test]
+    fn only_msvc_crts_are_trusted() {
+        assert!(is_msvc_crt("MSVCR120.dll"));
+        assert!(is_msvc_crt("ucrtbase.dll"));
+        assert!(is_msvc_crt("api-ms-win-crt-heap-l1-1-0.dll"));
+        assert!(!is_msvc_crt("msvcrt.dll"));
+        assert!(!is_msvc_crt("msvcr.dll"));
+        assert!(!is_msvc_crt("kernel32.dll"));
+    }
+
+    // A block laid out as std::allocator does for a big allocation, `shift` bytes after its start.
+    fn big_block(storage: &mut [usize], shift: usize) -> *mut c_void {
+        let base = storage.as_mut_ptr() as usize;
+        let user = (base + MIN_BACK_SHIFT).next_multiple_of(BIG_ALLOCATION_ALIGNMENT);
+        unsafe { *((user - MIN_BACK_SHIFT) as *mut usize) = user - shift };
+        user as *mut c_void
+    }
+
+    #[test]
+    fn big_allocation_is_freed_by_its_back_pointer() {
+        let mut storage = [0usize; 32];
+        let user = big_block(&mut storage, MIN_BACK_SHIFT);
+        assert_eq!(
+            unsafe { big_allocation_block(user) },
+            Some((user as usize - MIN_BACK_SHIFT) as *mut c_void)
+        );
+
+        let user = big_block(&mut storage, MAX_BACK_SHIFT + 1);
+        assert_eq!(unsafe { big_allocation_block(user) }, None);
+
+        // Not aligned, so not made by the big allocation path.
+        let misaligned = unsafe { (big_block(&mut storage, MIN_BACK_SHIFT) as *mut u8).add(4) };
+        assert_eq!(
+            unsafe { big_allocation_block(misaligned as *mut c_void) },
+            None
+        );
+    }
+
+    #[test]
+    fn empty_string() {
+        let s = std_string_of("");
+        assert_eq!(s.as_bytes(), b"");
+        assert_eq!(s.to_string(), "");
+    }
 }
@@ -18,8 +18,15 @@ pub enum Error {
     FunctionNotFound(String),
     MaybeInvalidGameOrPluginConflicting,
     SampNotLoaded(WindowsError),
+    ModuleNotLoaded(&'static str),
     IncompatibleSampVersion,
     IncompatibleMoonLoaderVersion(u32),
+    CallSitesNotFound(&'static str),
+    UnknownOffset(&'static str),
+    UnsupportedCode(usize),
+    InvalidAddress(usize),
+    IoError(std::io::Error),
+    JsonError(serde_json::Error),
 }
 
 impl fmt::Display for Error {
@@ -33,12 +40,21 @@ impl fmt::Display for Error {
                 write!(f, "Maybe invalid game or conflicting plugin")
             }
             Error::SampNotLoaded(e) => write!(f, "Library 'samp.dll' not found. WinAPI: {}", e),
+            Error::ModuleNotLoaded(name) => write!(f, "Module '{}' is not loaded", name),
             Error::IncompatibleSampVersion => write!(f, "Incompatible SA-MP version"),
             Error::IncompatibleMoonLoaderVersion(ep) => write!(
                 f,
                 "Incompatible MoonLoader version. Entry Point: {:#04X}",
                 ep
             ),
+            Error::CallSitesNotFound(function) => write!(f, "No calls of {} found", function),
+            Error::UnknownOffset(function) => {
+                write!(f, "Offset of {} is unknown for this version", function)
+            }
+            Error::UnsupportedCode(address) => write!(f, "Can't hook the code at {:#X}", address),
+            Error::InvalidAddress(address) => write!(f, "Can't access memory at {:#X}", address),
+            Error::IoError(e) => write!(f, "I/O: {}", e),
+            Error::JsonError(e) => write!(f, "JSON: {}", e),
         }
     }
 }
@@ -50,3 +66,15 @@ impl From<WindowsError> for Error {
         Error::WinApiError(e)
     }
 }
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::IoError(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::JsonError(e)
+    }
+}
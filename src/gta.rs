@@ -16,10 +16,51 @@ pub fn get_window_handle() -> HWND {
     unsafe { **(0xC17054 as *const *const HWND) }
 }
 
-pub fn get_d3d9_device() -> IDirect3DDevice9 {
-    unsafe { windows::core::Interface::from_raw(*(0xC97C28 as *const *mut c_void)) }
+pub fn get_d3d9_device() -> Option<IDirect3DDevice9> {
+    unsafe {
+        let device = *(0xC97C28 as *const *mut c_void);
+        if device.is_null() {
+            None
+        } else {
+            Some(windows::core::Interface::from_raw(device))
+        }
+    }
 }
 
+// gGameState value while in game, everything before it is startup or loading.
+const GAME_STATE_IDLE: u32 = 9;
+
+// CMenuManager::m_bMenuActive of FrontEndMenuManager, the frontend (Esc) menu.
 pub fn is_gta_menu_active() -> bool {
     unsafe { *(0xBA67A4 as *const bool) }
 }
+
+// CLoadingScreen::m_bActive
+pub fn is_loading_screen_active() -> bool {
+    unsafe { *(0xBAB31E as *const bool) }
+}
+
+// CCutsceneMgr::ms_running
+pub fn is_cutscene_running() -> bool {
+    unsafe { *(0xB5F851 as *const bool) }
+}
+
+// gGameState
+pub fn is_game_started() -> bool {
+    unsafe { *(0xC8D4C0 as *const u32) == GAME_STATE_IDLE }
+}
+
+// CTimer::m_UserPause
+pub fn is_game_paused() -> bool {
+    unsafe { *(0xB7CB49 as *const bool) }
+}
+
+/// The overlay must not be drawn over these screens: startup, the frontend menu,
+/// loading screens, cutscenes and pause.
+pub fn is_gameplay_interrupted() -> bool {
+    !is_game_started()
+        || is_gta_menu_active()
+        || is_loading_screen_active()
+        || is_cutscene_running()
+        || is_game_paused()
+}
@@ -9,19 +9,202 @@
  *
  *****************************************************************************/
 
-use crate::cmd_storage::CMD_PREFIX;
+use crate::cmd_storage::{
+    command_token, format_command_list, match_command, CategoryKey, CommandEntry, CommandMap,
+    Conflict, MatchMode, CMD_PREFIX,
+};
+use crate::config::{self, Anchor, Dock, DuplicateMode, UnmatchedMode, ViewMode};
 use crate::plugin::Plugin;
-use crate::{gta, samp};
+use crate::{gta, keys, moonloader, samp, sampfuncs, utils};
 use egui::{
-    epaint::Shadow, Color32, FontData, FontDefinitions, FontFamily, FontId, FontTweak, Key, Label,
-    RichText, Rounding, Sense, TextStyle,
+    epaint::Shadow, pos2, vec2, Align, Align2, Color32, Event, FontData, FontDefinitions,
+    FontFamily, FontId, FontTweak, Key, Label, Layout, Pos2, Rect, RichText, Rounding, Sense,
+    Stroke, TextStyle,
 };
-use std::ffi::CStr;
-use local_encoding::{Encoder, Encoding};
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::c_char;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const MAIN_FONT_NAME: &str = "Segoe UI Bold";
+const MAIN_FONT_FILE: &str = "segoeuib.ttf";
+
+// Written by the render path only, read by other plugins from any thread.
+static OVERLAY_VISIBLE: AtomicBool = AtomicBool::new(false);
+
+/// Whether the last frame has shown the overlay.
+pub fn is_overlay_visible() -> bool {
+    OVERLAY_VISIBLE.load(Ordering::Relaxed)
+}
+
+const INSERT_FLASH_TIME: f32 = 0.4;
+const REGISTERED_FADE_TIME: f32 = 1.0;
+
+// Alt+1..9 insert the matching commands in the order they are shown.
+const QUICK_INSERT_KEYS: [Key; 9] = [
+    Key::Num1,
+    Key::Num2,
+    Key::Num3,
+    Key::Num4,
+    Key::Num5,
+    Key::Num6,
+    Key::Num7,
+    Key::Num8,
+    Key::Num9,
+];
 
 pub struct Ui {
     cmds_height: f32,
     cmds_width: f32,
+    settings_open: bool,
+    diagnostics_open: bool,
+    style_revision: u32,
+    // Seconds of egui time.
+    last_interaction: f64,
+    // Command and the note being edited.
+    note_editor: Option<(String, String)>,
+    // Command waiting for the user to confirm inserting it.
+    confirmation: Option<String>,
+    filter_cache: FilterCache,
+    // By the id of the category list, so that reopening the list keeps the place in it.
+    scroll_offsets: HashMap<egui::Id, ScrollOffset>,
+    // Set for windows that grow upwards, they can't take more than the space above their bottom.
+    max_list_height: Option<f32>,
+    // With the filter revision they have been found for.
+    conflicts: Option<(u32, Vec<Conflict>)>,
+}
+
+struct ScrollOffset {
+    // A different query shows different commands, the offset means nothing then.
+    query: String,
+    offset: f32,
+    frame: u64,
+}
+
+// Commands of a module with whether they match, matching ones first.
+type FilteredCommands = Vec<(String, bool)>;
+
+// Filtering every module each frame is noticeable with many commands, so the result is kept
+// until the query or the commands change.
+#[derive(Default)]
+struct FilterCache {
+    query: String,
+    revision: u32,
+    // By category and module.
+    modules: HashMap<String, HashMap<String, FilteredCommands>>,
+}
+
+impl FilterCache {
+    fn validate(&mut self, query: &str, revision: u32) {
+        if self.query != query || self.revision != revision {
+            self.query = query.to_string();
+            self.revision = revision;
+            self.modules.clear();
+        }
+    }
+
+    fn get_or_filter(
+        &mut self,
+        category: &str,
+        module: &str,
+        filter: impl FnOnce() -> FilteredCommands,
+    ) -> &FilteredCommands {
+        if !self.modules.contains_key(category) {
+            self.modules.insert(category.to_string(), HashMap::new());
+        }
+        let modules = self.modules.get_mut(category).unwrap();
+        if !modules.contains_key(module) {
+            modules.insert(module.to_string(), filter());
+        }
+        &modules[module]
+    }
+}
+
+/// What the list above the chat shows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShouldRender {
+    Hidden,
+    ShowCommands,
+    ShowRecalls,
+    /// All commands while the chat is closed.
+    ShowPinned,
+}
+
+impl ShouldRender {
+    pub fn is_visible(self) -> bool {
+        self != ShouldRender::Hidden
+    }
+}
+
+/// Everything the list depends on, collected once per frame.
+pub struct RenderConditions {
+    /// The pause menu or another screen that interrupts the gameplay is open.
+    pub menu_open: bool,
+    /// The game window is not focused and the overlay is hidden with it.
+    pub unfocused: bool,
+    /// F5 or F10 is held, SA-MP draws its own windows over the chat.
+    pub suppressed: bool,
+    pub chat_open: bool,
+    /// The user wants the commands all the time, even with the chat closed.
+    pub pinned: bool,
+    /// The chat text starts with the command prefix.
+    pub is_command: bool,
+    /// Characters of the typed command after the prefix.
+    pub query_length: usize,
+    pub min_query_length: usize,
+    pub idle: bool,
+    /// Hidden with Escape, with the toggle key or after inserting the typed command.
+    pub list_dismissed: bool,
+    pub overlay_hidden: bool,
+    pub input_dismissed: bool,
+    pub has_recalls: bool,
+    pub commands_ready: bool,
+    pub has_commands: bool,
+}
+
+impl RenderConditions {
+    /// Nothing is drawn over these screens, not even the settings.
+    pub fn is_blocked(&self) -> bool {
+        self.menu_open || self.unfocused || self.suppressed
+    }
+}
+
+pub fn should_render(c: &RenderConditions) -> ShouldRender {
+    if c.is_blocked() || c.overlay_hidden {
+        return ShouldRender::Hidden;
+    }
+
+    if !c.chat_open {
+        return if c.pinned && c.commands_ready {
+            ShouldRender::ShowPinned
+        } else {
+            ShouldRender::Hidden
+        };
+    }
+
+    if c.idle || c.list_dismissed {
+        return ShouldRender::Hidden;
+    }
+
+    if !c.is_command {
+        // Recalls don't depend on the commands, so they are shown anyway.
+        return if c.has_recalls {
+            ShouldRender::ShowRecalls
+        } else {
+            ShouldRender::Hidden
+        };
+    }
+
+    // A command has just been inserted with Enter, or the user wants the list only after typing
+    // the beginning of a command. Don't draw empty list.
+    if c.input_dismissed
+        || c.query_length < c.min_query_length
+        || !c.commands_ready
+        || !c.has_commands
+    {
+        return ShouldRender::Hidden;
+    }
+    ShouldRender::ShowCommands
 }
 
 impl Ui {
@@ -29,102 +212,259 @@ impl Ui {
         Self {
             cmds_height: 64.0,
             cmds_width: 64.0,
+            settings_open: false,
+            diagnostics_open: false,
+            style_revision: 0,
+            last_interaction: 0.0,
+            note_editor: None,
+            confirmation: None,
+            filter_cache: FilterCache::default(),
+            scroll_offsets: HashMap::new(),
+            max_list_height: None,
+            conflicts: None,
         }
     }
 
     pub fn init_style(ctx: &egui::Context) {
         Self::setup_custom_fonts(ctx);
-        Self::configure_text_styles(ctx);
         Self::configure_visuals(ctx);
+        Self::apply_config_style(ctx);
+    }
+
+    // Style that depends on settings and can be changed at runtime.
+    fn apply_config_style(ctx: &egui::Context) {
+        let config = Plugin::get().config();
+
+        let mut style = (*ctx.style()).clone();
+        style.text_styles = Self::text_styles(config.ui_scale());
+        // Labels keep their own height, so click targets stay usable with zero spacing.
+        style.spacing.item_spacing.y = config
+            .item_spacing
+            .clamp(config::MIN_ITEM_SPACING, config::MAX_ITEM_SPACING);
+        style.visuals.window_shadow = if config.window_shadow {
+            Shadow::small_dark()
+        } else {
+            Shadow::NONE
+        };
+        style.visuals.window_stroke = if config.window_border {
+            let [r, g, b, a] = config.window_border_color;
+            Stroke::new(1.0, Color32::from_rgba_unmultiplied(r, g, b, a))
+        } else {
+            egui::Visuals::dark().window_stroke
+        };
+        ctx.set_style(style);
     }
 
-    fn add_font(fonts: &mut FontDefinitions, name: &str, font: &'static [u8]) {
+    fn add_font(fonts: &mut FontDefinitions, name: &str, font: FontData, priority: usize) {
         let name = name.to_string();
         let tweak = FontTweak::default();
-        fonts
-            .font_data
-            .insert(name.clone(), FontData::from_static(font).tweak(tweak));
+        fonts.font_data.insert(name.clone(), font.tweak(tweak));
+        let proportional = fonts.families.entry(FontFamily::Proportional).or_default();
+        proportional.insert(priority.min(proportional.len()), name.clone());
         fonts
             .families
-            .get_mut(&FontFamily::Proportional)
-            .unwrap()
-            .insert(0, name.clone());
-        fonts
-            .families
-            .get_mut(&FontFamily::Monospace)
-            .unwrap()
+            .entry(FontFamily::Monospace)
+            .or_default()
             .push(name);
     }
 
-    fn setup_custom_fonts(ctx: &egui::Context) {
+    fn is_font_data(data: &[u8]) -> bool {
+        matches!(
+            data.get(..4),
+            Some([0x00, 0x01, 0x00, 0x00]) | Some(b"OTTO") | Some(b"true") | Some(b"ttcf")
+        )
+    }
+
+    // Missing files are fine, the fonts are optional.
+    fn load_font_file(path: &Path) -> Option<FontData> {
+        let data = match std::fs::read(path) {
+            Ok(data) => data,
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    eprintln!("gui::load_font_file: {}: {}", path.display(), e);
+                }
+                return None;
+            }
+        };
+
+        // egui panics on invalid font data, so check at least the signature.
+        if !Self::is_font_data(&data) {
+            eprintln!("gui::load_font_file: {}: not a font file", path.display());
+            return None;
+        }
+
+        Some(FontData::from_owned(data))
+    }
+
+    // Without the main font the default fonts of egui are used, which are enough to show the commands.
+    // Additional fonts are fallbacks for glyphs missing in the main one, e.g. CJK.
+    fn font_definitions(
+        main_font: Option<FontData>,
+        additional: Vec<(String, FontData)>,
+    ) -> FontDefinitions {
         let mut fonts = FontDefinitions::default();
-        Self::add_font(
-            &mut fonts,
-            "Segoe UI Bold",
-            include_bytes!("C:\\Windows\\Fonts\\segoeuib.ttf"),
-        );
-        ctx.set_fonts(fonts);
+        if let Some(font) = main_font {
+            Self::add_font(&mut fonts, MAIN_FONT_NAME, font, 0);
+        }
+        for (priority, (name, font)) in additional.into_iter().enumerate() {
+            Self::add_font(&mut fonts, &name, font, priority + 1);
+        }
+        fonts
+    }
+
+    fn setup_custom_fonts(ctx: &egui::Context) {
+        let main_font = utils::get_windows_directory()
+            .and_then(|dir| Self::load_font_file(&dir.join("Fonts").join(MAIN_FONT_FILE)));
+        if main_font.is_none() {
+            eprintln!(
+                "gui::setup_custom_fonts: {} can't be loaded, the default font is used",
+                MAIN_FONT_FILE
+            );
+        }
+
+        let plugin = Plugin::get();
+        let fonts_dir = plugin.data_dir().join(config::FONTS_DIRECTORY);
+        let additional = plugin
+            .config()
+            .fonts
+            .iter()
+            .filter_map(|file_name| {
+                let font = Self::load_font_file(&fonts_dir.join(file_name))?;
+                Some((file_name.clone(), font))
+            })
+            .collect();
+
+        ctx.set_fonts(Self::font_definitions(main_font, additional));
     }
 
-    fn configure_text_styles(ctx: &egui::Context) {
+    // Scaling the fonts rather than pixels_per_point keeps the overlay in the coordinates of the game.
+    fn text_styles(scale: f32) -> BTreeMap<TextStyle, FontId> {
         use FontFamily::{Monospace, Proportional};
 
-        let mut style = (*ctx.style()).clone();
-        style.text_styles = [
-            (TextStyle::Heading, FontId::new(24.0, Proportional)),
-            (TextStyle::Body, FontId::new(16.5, Proportional)),
-            (TextStyle::Monospace, FontId::new(16.0, Monospace)),
-            (TextStyle::Button, FontId::new(16.5, Proportional)),
-            (TextStyle::Small, FontId::new(8.0, Proportional)),
+        [
+            (TextStyle::Heading, FontId::new(24.0 * scale, Proportional)),
+            (TextStyle::Body, FontId::new(16.5 * scale, Proportional)),
+            (TextStyle::Monospace, FontId::new(16.0 * scale, Monospace)),
+            (TextStyle::Button, FontId::new(16.5 * scale, Proportional)),
+            (TextStyle::Small, FontId::new(8.0 * scale, Proportional)),
         ]
-        .into();
-        ctx.set_style(style);
+        .into()
     }
 
     fn configure_visuals(ctx: &egui::Context) {
         let mut visuals = ctx.style().visuals.clone();
-        visuals.window_shadow = Shadow::NONE;
         visuals.window_fill = Color32::from_rgba_premultiplied(20, 20, 20, 200);
         visuals.window_rounding = Rounding::same(10.);
         ctx.set_visuals(visuals);
     }
 
     pub fn render_ui(ctx: &egui::Context, this: &mut Ui) {
-        if gta::is_gta_menu_active() {
-            return;
-        }
+        let plugin = Plugin::get();
+        plugin.clear_overlay_rects();
+        plugin.clear_selectable_commands();
+        plugin.set_list_shown(false);
+        OVERLAY_VISIBLE.store(false, Ordering::Relaxed);
 
-        // SA-MP keys.
-        // Todo: It might be better to read the key from memory, in case there is a plugin to change the keys.
-        if ctx.input(|i| i.key_down(Key::F5) || i.key_down(Key::F10)) {
-            return;
+        if this.style_revision != plugin.style_revision() {
+            this.style_revision = plugin.style_revision();
+            Self::apply_config_style(ctx);
         }
 
+        this.track_interaction(ctx);
+
         let samp_input = match samp::Input::get() {
             Some(v) => v,
             None => return,
         };
 
-        // Draw only if chat input is open.
-        if !samp_input.enabled.as_bool() {
+        let chat_open = samp_input.enabled.as_bool();
+        let chat_input = if chat_open {
+            samp_input.edit_box().get_text()
+        } else {
+            String::new()
+        };
+        let chat_contains_cmd = chat_input.starts_with(CMD_PREFIX);
+        // Arguments don't change which command is typed.
+        let query = command_token(&chat_input).to_string();
+
+        let conditions = RenderConditions {
+            menu_open: gta::is_gameplay_interrupted(),
+            unfocused: plugin.config().hide_on_focus_loss && !plugin.is_window_focused(),
+            // Todo: It might be better to read the key from memory, in case there is a plugin to change the keys.
+            suppressed: ctx.input(|i| keys::SUPPRESSION_KEYS.iter().any(|&key| i.key_down(key))),
+            chat_open,
+            pinned: plugin.config().pinned,
+            is_command: chat_contains_cmd,
+            query_length: query
+                .get(CMD_PREFIX.len()..)
+                .map_or(0, |q| q.chars().count()),
+            min_query_length: plugin.config().min_query_length,
+            idle: this.is_idle(ctx),
+            list_dismissed: plugin.is_list_dismissed(),
+            overlay_hidden: plugin.is_overlay_hidden(),
+            input_dismissed: chat_contains_cmd && plugin.is_overlay_dismissed(&chat_input),
+            has_recalls: samp_input.total_recall != 0,
+            commands_ready: plugin.commands_ready(),
+            has_commands: !plugin.commands().is_empty(),
+        };
+        if conditions.is_blocked() {
             return;
         }
 
-        let chat_input = samp_input.edit_box().get_text();
-        let chat_contains_cmd = chat_input.starts_with(CMD_PREFIX);
+        if plugin.take_settings_request() {
+            this.settings_open = true;
+        }
+        if this.settings_open {
+            this.draw_settings(ctx, samp_input);
+        }
+        if this.diagnostics_open {
+            this.draw_diagnostics(ctx);
+        }
+        if !plugin.config().tutorial_shown {
+            Self::draw_tutorial(ctx);
+        }
+        if let Some(cmd) = Self::take_note_request(ctx) {
+            let note = plugin.note_of(&cmd).unwrap_or_default().to_string();
+            this.note_editor = Some((cmd, note));
+        }
+        if this.note_editor.is_some() {
+            this.draw_note_editor(ctx);
+        }
+        if let Some(cmd) = Self::take_confirmation_request(ctx) {
+            this.confirmation = Some(cmd);
+        }
+        if this.confirmation.is_some() {
+            this.draw_confirmation(ctx, samp_input);
+        }
 
-        // Don't draw empty list.
-        if (samp_input.total_recall == 0 && !chat_contains_cmd)
-            || (chat_contains_cmd && Plugin::get().commands().is_empty())
-        {
-            return;
+        if !chat_open {
+            plugin.chat_closed();
+        } else if chat_contains_cmd && !conditions.overlay_hidden {
+            Self::draw_template_hint(ctx, samp_input, &chat_input);
         }
 
-        let pos = samp_input.edit_box().position;
-        let pos = [
-            pos[0] as f32,
-            (pos[1] + samp_input.edit_box().height + 5) as f32,
-        ];
+        let decision = should_render(&conditions);
+        OVERLAY_VISIBLE.store(decision.is_visible(), Ordering::Relaxed);
+        let chat_contains_cmd = match decision {
+            ShouldRender::Hidden => return,
+            ShouldRender::ShowCommands => true,
+            ShouldRender::ShowRecalls => false,
+            ShouldRender::ShowPinned => {
+                this.draw_pinned(ctx, samp_input);
+                return;
+            }
+        };
+
+        let config = plugin.config();
+        let (pos, pivot) = if chat_contains_cmd {
+            this.calc_window_position(ctx, samp_input, config.anchor)
+        } else {
+            let (pos, pivot) = this.calc_window_position(ctx, samp_input, config.recall_anchor);
+            (
+                pos + vec2(config.recall_offset[0], config.recall_offset[1]),
+                pivot,
+            )
+        };
 
         // So that each window has its own size.
         let key = if chat_contains_cmd {
@@ -132,22 +472,264 @@ impl Ui {
         } else {
             "Recalls"
         };
-        let width = this.calc_chat_input_width(samp_input);
-        egui::containers::Window::new(key)
+        let (width, dock) = if chat_contains_cmd {
+            (this.calc_cmds_window_width(samp_input), config.dock)
+        } else {
+            // Recalls belong to the chat input.
+            (this.calc_chat_input_width(samp_input), Dock::Floating)
+        };
+        this.max_list_height = Self::calc_max_list_height(dock, pos, pivot);
+        let rect = Self::show_overlay(ctx, key, dock, (pos, pivot), width, |ui| {
+            if chat_contains_cmd {
+                this.draw_commands(ui, &query, samp_input)
+            } else {
+                this.draw_recalls(ui, samp_input);
+            }
+            this.draw_copyright(ui);
+        });
+
+        if let Some(rect) = rect {
+            Self::track_overlay_rect(ctx, rect);
+            plugin.set_list_shown(true);
+        }
+
+        if chat_contains_cmd && plugin.config().quick_insert {
+            Self::handle_quick_insert(ctx);
+        }
+    }
+
+    // All commands at a fixed place while the chat is closed.
+    fn draw_pinned(&mut self, ctx: &egui::Context, input: &mut samp::Input) {
+        let (pos, pivot) =
+            self.calc_window_position(ctx, input, Plugin::get().config().pinned_anchor);
+        let width = self.calc_cmds_window_width(input);
+        let dock = Plugin::get().config().dock;
+        self.max_list_height = Self::calc_max_list_height(dock, pos, pivot);
+        let rect = Self::show_overlay(ctx, "Pinned", dock, (pos, pivot), width, |ui| {
+            self.draw_commands(ui, &String::new(), input);
+        });
+
+        if let Some(rect) = rect {
+            Self::track_overlay_rect(ctx, rect);
+        }
+    }
+
+    // A window at the position, or a panel that takes the edge of the screen.
+    // Returns the rect taken by the contents, the rest of a panel doesn't block the game.
+    fn show_overlay(
+        ctx: &egui::Context,
+        key: &str,
+        dock: Dock,
+        (pos, pivot): (Pos2, Align2),
+        width: f32,
+        add_contents: impl FnOnce(&mut egui::Ui),
+    ) -> Option<Rect> {
+        if dock == Dock::Floating {
+            return egui::containers::Window::new(key)
+                .fixed_pos(pos)
+                .pivot(pivot)
+                .min_width(width)
+                .max_width(width)
+                .title_bar(false)
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, add_contents)
+                .map(|response| response.response.rect);
+        }
+
+        let id = egui::Id::new(key);
+        let frame = egui::Frame::side_top_panel(&ctx.style());
+        let contents = |ui: &mut egui::Ui| {
+            // Top and bottom panels span the screen, the list keeps its width.
+            ui.set_max_width(width);
+            add_contents(ui);
+            ui.min_rect()
+        };
+        let contents_rect = match dock {
+            Dock::Floating => unreachable!(),
+            Dock::Left => {
+                egui::SidePanel::left(id)
+                    .exact_width(width)
+                    .resizable(false)
+                    .frame(frame)
+                    .show(ctx, contents)
+                    .inner
+            }
+            Dock::Right => {
+                egui::SidePanel::right(id)
+                    .exact_width(width)
+                    .resizable(false)
+                    .frame(frame)
+                    .show(ctx, contents)
+                    .inner
+            }
+            Dock::Top => {
+                egui::TopBottomPanel::top(id)
+                    .resizable(false)
+                    .frame(frame)
+                    .show(ctx, contents)
+                    .inner
+            }
+            Dock::Bottom => {
+                egui::TopBottomPanel::bottom(id)
+                    .resizable(false)
+                    .frame(frame)
+                    .show(ctx, contents)
+                    .inner
+            }
+        };
+        let margin = frame.inner_margin;
+        Some(Rect::from_min_max(
+            contents_rect.min - margin.left_top(),
+            contents_rect.max + margin.right_bottom(),
+        ))
+    }
+
+    // Parameters of the inserted command above the chat input, the one being typed is highlighted.
+    fn draw_template_hint(ctx: &egui::Context, input: &mut samp::Input, chat_input: &str) {
+        let Some((command, params, current)) = Plugin::get().active_template(chat_input) else {
+            return;
+        };
+
+        // Out of the way of the list.
+        let above = Self::is_list_above_chat(ctx, input);
+        let eb = input.edit_box();
+        let (pos, pivot) = if above {
+            (
+                pos2(
+                    eb.position[0] as f32,
+                    (eb.position[1] + eb.height) as f32 + 4.0,
+                ),
+                Align2::LEFT_TOP,
+            )
+        } else {
+            (
+                pos2(eb.position[0] as f32, eb.position[1] as f32 - 4.0),
+                Align2::LEFT_BOTTOM,
+            )
+        };
+        let response = egui::Area::new("template_hint")
             .fixed_pos(pos)
-            .min_width(width)
-            .max_width(width)
-            .title_bar(false)
-            .collapsible(false)
-            .resizable(false)
+            .pivot(pivot)
+            .interactable(false)
             .show(ctx, |ui| {
-                if chat_contains_cmd {
-                    this.draw_commands(ui, &chat_input, samp_input)
-                } else {
-                    this.draw_recalls(ui, samp_input);
-                }
-                this.draw_copyright(ui);
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label(command);
+                        for (i, param) in params.iter().enumerate() {
+                            let text = RichText::new(format!("<{}>", param));
+                            if i == current {
+                                ui.label(
+                                    text.strong()
+                                        .background_color(ui.visuals().selection.bg_fill),
+                                );
+                            } else {
+                                ui.weak(text);
+                            }
+                        }
+                    });
+                });
             });
+        Self::track_overlay_rect(ctx, response.response.rect);
+    }
+
+    fn handle_quick_insert(ctx: &egui::Context) {
+        let pressed = ctx.input(|i| {
+            if !i.modifiers.alt {
+                return None;
+            }
+            QUICK_INSERT_KEYS.iter().position(|&key| i.key_pressed(key))
+        });
+        if let Some(index) = pressed {
+            Plugin::get().insert_selectable_command(index, false);
+        }
+    }
+
+    fn track_interaction(&mut self, ctx: &egui::Context) {
+        let interacted = ctx.input(|i| {
+            i.events.iter().any(|e| {
+                matches!(
+                    e,
+                    Event::Key { .. }
+                        | Event::Text(_)
+                        | Event::PointerButton { .. }
+                        | Event::Scroll(_)
+                )
+            })
+        });
+        if interacted {
+            self.last_interaction = ctx.input(|i| i.time);
+        }
+    }
+
+    // The overlay hides itself after some time without typing if the user wants so.
+    fn is_idle(&self, ctx: &egui::Context) -> bool {
+        let timeout = Plugin::get().config().auto_hide_seconds;
+        timeout > 0.0 && ctx.input(|i| i.time) - self.last_interaction > timeout as f64
+    }
+
+    // Lets the window procedure know where our windows are to keep mouse input from the game.
+    fn track_overlay_rect(ctx: &egui::Context, rect: Rect) {
+        let ppp = ctx.pixels_per_point();
+        Plugin::get().add_overlay_rect(Rect::from_min_max(
+            pos2(rect.min.x * ppp, rect.min.y * ppp),
+            pos2(rect.max.x * ppp, rect.max.y * ppp),
+        ));
+    }
+
+    fn calc_window_position(
+        &self,
+        ctx: &egui::Context,
+        input: &mut samp::Input,
+        anchor: Anchor,
+    ) -> (Pos2, Align2) {
+        let margin = Plugin::get().config().anchor_margin;
+        let screen = ctx.screen_rect();
+
+        match anchor {
+            Anchor::FollowChat if Self::is_list_above_chat(ctx, input) => {
+                let eb = input.edit_box();
+                let pos = pos2(eb.position[0] as f32, (eb.position[1] - 5) as f32);
+                (pos, Align2::LEFT_BOTTOM)
+            }
+            Anchor::FollowChat => {
+                let eb = input.edit_box();
+                let pos = pos2(
+                    eb.position[0] as f32,
+                    (eb.position[1] + eb.height + 5) as f32,
+                );
+                (pos, Align2::LEFT_TOP)
+            }
+            Anchor::TopLeft => (screen.left_top() + vec2(margin, margin), Align2::LEFT_TOP),
+            Anchor::TopRight => (
+                screen.right_top() + vec2(-margin, margin),
+                Align2::RIGHT_TOP,
+            ),
+            Anchor::BottomLeft => (
+                screen.left_bottom() + vec2(margin, -margin),
+                Align2::LEFT_BOTTOM,
+            ),
+            Anchor::BottomRight => (
+                screen.right_bottom() + vec2(-margin, -margin),
+                Align2::RIGHT_BOTTOM,
+            ),
+        }
+    }
+
+    // Near the bottom of the screen the list would be cut off below the chat input.
+    fn is_list_above_chat(ctx: &egui::Context, input: &samp::Input) -> bool {
+        if !Plugin::get().config().flip_above_chat {
+            return false;
+        }
+        let eb = input.edit_box();
+        let screen = ctx.screen_rect();
+        let above = eb.position[1] as f32 - screen.top();
+        let below = screen.bottom() - (eb.position[1] + eb.height) as f32;
+        above > below
+    }
+
+    fn calc_max_list_height(dock: Dock, pos: Pos2, pivot: Align2) -> Option<f32> {
+        (dock == Dock::Floating && pivot.y() == Align::Max).then(|| pos.y - 100.)
     }
 
     fn calc_chat_input_width(&self, input: &mut samp::Input) -> f32 {
@@ -156,8 +738,20 @@ impl Ui {
     }
 
     fn calc_cmds_col_width(&self, input: &mut samp::Input) -> f32 {
-        let columns_count = Plugin::get().commands().category_count();
-        self.calc_chat_input_width(input) / columns_count as f32
+        let columns_count = Plugin::get().commands().category_count().max(1);
+        let config = Plugin::get().config();
+        let min_width = config.min_column_width * config.ui_scale();
+        (self.calc_chat_input_width(input) / columns_count as f32).max(min_width)
+    }
+
+    // Columns may be wider than the chat if the user asked for it.
+    fn calc_cmds_window_width(&self, input: &mut samp::Input) -> f32 {
+        if Plugin::get().config().view_mode != ViewMode::Categories {
+            return self.calc_chat_input_width(input);
+        }
+        let columns_count = Plugin::get().commands().category_count().max(1);
+        self.calc_chat_input_width(input)
+            .max(self.calc_cmds_col_width(input) * columns_count as f32)
     }
 
     fn draw_commands(
@@ -166,6 +760,32 @@ impl Ui {
         chat_input: &String,
         samp_input: &mut samp::Input,
     ) {
+        let plugin = Plugin::get();
+        if plugin.config().show_server_address {
+            if let Some(server_address) = plugin.server_address() {
+                ui.vertical_centered(|ui| {
+                    ui.weak(server_address);
+                });
+            }
+        }
+
+        // Nothing is said while it is unknown.
+        if samp::are_commands_enabled() == Some(false) {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                "The server has disabled client commands",
+            );
+        }
+
+        if plugin.config().chat_suggestions && !plugin.chat_suggestions().is_empty() {
+            self.draw_chat_suggestions(ui, chat_input, samp_input);
+        }
+
+        if plugin.config().view_mode != ViewMode::Categories {
+            self.draw_unified_commands(ui, chat_input, samp_input);
+            return;
+        }
+
         self.cmds_width = self.calc_cmds_col_width(samp_input);
 
         egui::Grid::new("cmds")
@@ -180,6 +800,23 @@ impl Ui {
             });
     }
 
+    fn draw_chat_suggestions(
+        &mut self,
+        ui: &mut egui::Ui,
+        chat_input: &String,
+        input: &mut samp::Input,
+    ) {
+        let match_mode = Plugin::get().config().match_mode;
+        ui.horizontal_wrapped(|ui| {
+            ui.weak("From chat:");
+            for cmd in Plugin::get().chat_suggestions() {
+                let matched = match_command(chat_input, cmd, match_mode).is_some();
+                Self::draw_command(ui, input, cmd, matched, true);
+            }
+        });
+        ui.separator();
+    }
+
     fn draw_cmds_header(&self, ui: &mut egui::Ui) {
         for category in Plugin::get().commands().iter() {
             if category.is_visible {
@@ -193,92 +830,1569 @@ impl Ui {
     fn draw_cmds_body(&mut self, ui: &mut egui::Ui, chat_input: &String, input: &mut samp::Input) {
         let cursor_top = ui.cursor().top();
         let mut max_content_height = 0.;
+        let match_mode = Plugin::get().config().match_mode;
+        let strip_extensions = Plugin::get().config().strip_module_extensions;
+
+        let unfiltered = &Plugin::get().config().unfiltered_categories;
+        self.filter_cache
+            .validate(chat_input, Plugin::get().filter_revision());
 
         for category in Plugin::get().commands().iter() {
             if !category.is_visible {
                 continue;
             }
+            // As if nothing has been typed.
+            let query = if unfiltered.contains(&category.name) {
+                ""
+            } else {
+                chat_input.as_str()
+            };
 
-            let content_height = egui::ScrollArea::vertical()
+            let frame = ui.ctx().frame_nr();
+            let scroll_id = ui.id().with(&category.name);
+            let output = self
+                .restored_scroll_area(scroll_id, query, frame)
                 .id_source(&category.name)
                 .min_scrolled_height(self.cmds_height)
                 .show(ui, |ui| {
                     ui.set_min_width(self.cmds_width);
                     ui.vertical(|ui| {
+                        // The header has an icon before the title.
+                        let title_width = self.cmds_width
+                            - ui.spacing().icon_width
+                            - ui.spacing().icon_spacing
+                            - ui.spacing().item_spacing.x;
                         for (name, commands) in category.modules.iter() {
-                            egui::CollapsingHeader::new(name)
-                                .default_open(true)
-                                .show(ui, |ui| {
-                                    for (cmd, description) in commands.iter() {
-                                        let text = if chat_input.is_empty()
-                                            || cmd.starts_with(chat_input)
-                                        {
-                                            RichText::new(cmd)
-                                        } else {
-                                            RichText::new(cmd).weak()
-                                        };
-
-                                        let label = ui.add(Label::new(text).sense(Sense::click()));
-
-                                        if label.clicked() {
-                                            input.edit_box().set_text(cmd.as_str());
-                                        }
-
-                                        if !description.is_empty() {
-                                            label.on_hover_text(description);
-                                        }
-                                    }
-                                });
+                            let display_name = if strip_extensions {
+                                Self::strip_module_extension(name)
+                            } else {
+                                name
+                            };
+                            let title = Self::ellipsize(ui, display_name, title_width);
+                            let header = egui::CollapsingHeader::new(
+                                title.as_deref().unwrap_or(display_name),
+                            )
+                            .id_source(name)
+                            .default_open(true)
+                            .show(ui, |ui| {
+                                let filtered =
+                                    self.filter_cache.get_or_filter(&category.name, name, || {
+                                        Self::filter_module_commands(query, match_mode, commands)
+                                    });
+                                Self::draw_module_commands(
+                                    ui,
+                                    input,
+                                    filtered,
+                                    commands,
+                                    category.is_registered,
+                                    &format!("{}: {}", category.source_of(name), name),
+                                );
+                            });
+                            let header_response = if title.is_some() || display_name != name {
+                                header.header_response.on_hover_text(name)
+                            } else {
+                                header.header_response
+                            };
+                            header_response.context_menu(|ui| {
+                                if ui.button("Ignore module").clicked() {
+                                    Plugin::get().toggle_ignored_module(name);
+                                    ui.close_menu();
+                                }
+                            });
                         }
                     });
-                })
-                .content_size
-                .y;
+                });
+
+            let offset = output.state.offset.y;
+            match self.scroll_offsets.get_mut(&scroll_id) {
+                Some(scroll) if scroll.query == query => {
+                    scroll.offset = offset;
+                    scroll.frame = frame;
+                }
+                _ => {
+                    self.scroll_offsets.insert(
+                        scroll_id,
+                        ScrollOffset {
+                            query: query.to_string(),
+                            offset,
+                            frame,
+                        },
+                    );
+                }
+            }
 
+            let content_height = output.content_size.y;
             if content_height > max_content_height {
                 max_content_height = content_height;
             }
         }
 
-        let max_screen_height = ui.input(|i| i.screen_rect.height()) - cursor_top - 100.;
+        let max_screen_height = self
+            .max_list_height
+            .unwrap_or_else(|| ui.input(|i| i.screen_rect.height()) - cursor_top - 100.);
         self.cmds_height = max_content_height.min(max_screen_height);
     }
 
-    fn draw_copyright(&self, ui: &mut egui::Ui) {
-        ui.separator();
-        ui.vertical_centered(|ui| {
-            ui.strong("Copyright © Rinat Namazov").on_hover_ui(|ui| {
-                ui.label(concat!("SA-MP Command Helper v", env!("CARGO_PKG_VERSION")));
-                ui.label("https://rinwares.com");
-            });
-        });
+    fn restored_scroll_area(&self, id: egui::Id, query: &str, frame: u64) -> egui::ScrollArea {
+        let scroll_area = egui::ScrollArea::vertical();
+        match self.scroll_offsets.get(&id) {
+            Some(scroll) if scroll.query != query => scroll_area.vertical_scroll_offset(0.0),
+            // Not shown in the previous frame.
+            Some(scroll) if scroll.frame + 1 != frame => {
+                scroll_area.vertical_scroll_offset(scroll.offset)
+            }
+            _ => scroll_area,
+        }
     }
 
-    fn draw_recalls(&self, ui: &mut egui::Ui, input: &mut samp::Input) {
-        ui.vertical_centered(|ui| {
-            ui.strong("Recalls");
-        });
+    fn strip_module_extension(name: &str) -> &str {
+        const EXTENSIONS: [&str; 4] = [".dll", ".asi", ".cs", ".cleo"];
+        EXTENSIONS
+            .iter()
+            .find_map(|ext| {
+                // Bytes, since the name may end in the middle of a multibyte character.
+                let stem_len = name.len().checked_sub(ext.len()).filter(|&l| l > 0)?;
+                name.as_bytes()[stem_len..]
+                    .eq_ignore_ascii_case(ext.as_bytes())
+                    .then(|| &name[..stem_len])
+            })
+            .unwrap_or(name)
+    }
 
-        ui.indent(ui.id(), |ui| {
-            for i in 0..input.total_recall as usize {
-                if let Ok(recall) = CStr::from_bytes_until_nul(&input.recall_buffer[i]) {
-                    if let Ok(text) = Encoding::ANSI.to_string(recall.to_bytes_with_nul()) {
-                        let text =
-                            if input.current_recall == -1 || i == input.current_recall as usize {
-                                RichText::new(text)
-                            } else {
-                                RichText::new(text).weak()
-                            };
+    // Shortened text that fits into the width, or None if the text fits as it is.
+    fn ellipsize(ui: &egui::Ui, text: &str, max_width: f32) -> Option<String> {
+        let font_id = egui::TextStyle::Button.resolve(ui.style());
+        let width = |text: String| {
+            ui.fonts(|f| {
+                f.layout_no_wrap(text, font_id.clone(), egui::Color32::WHITE)
+                    .size()
+                    .x
+            })
+        };
 
-                        let label = ui.add(Label::new(text).sense(Sense::click()));
+        if width(text.to_string()) <= max_width {
+            return None;
+        }
 
-                        if label.clicked() {
-                            input.current_recall = i as i32;
-                            input.edit_box().set_text_raw(recall.as_ptr());
-                        }
-                    }
-                }
+        let mut chars: Vec<char> = text.chars().collect();
+        while !chars.is_empty() {
+            chars.pop();
+            let shortened = chars.iter().collect::<String>() + "…";
+            if width(shortened.clone()) <= max_width {
+                return Some(shortened);
+            }
+        }
+        Some("…".to_string())
+    }
+
+    fn filter_module_commands(
+        chat_input: &str,
+        match_mode: MatchMode,
+        commands: &CommandMap,
+    ) -> FilteredCommands {
+        let mut shown: FilteredCommands = commands
+            .keys()
+            .filter(|cmd| Self::is_command_shown(cmd))
+            .map(|cmd| {
+                let matched = match_command(chat_input, cmd, match_mode).is_some();
+                (cmd.clone(), matched)
+            })
+            .collect();
+        // Matching commands first, so that the limit doesn't hide them.
+        shown.sort_by_key(|(_, matched)| !matched);
+        shown
+    }
+
+    fn draw_module_commands(
+        ui: &mut egui::Ui,
+        input: &mut samp::Input,
+        filtered: &[(String, bool)],
+        commands: &CommandMap,
+        registered: bool,
+        source: &str,
+    ) {
+        let mut shown: Vec<_> = filtered
+            .iter()
+            .map(|(cmd, matched)| {
+                let description = commands.get(cmd).map_or("", String::as_str);
+                (cmd.as_str(), description, *matched)
+            })
+            .collect();
+        let matched_count = shown.iter().filter(|(_, _, matched)| *matched).count();
+        let unmatched_mode = Plugin::get().config().unmatched_mode;
+        if unmatched_mode == UnmatchedMode::Hidden {
+            shown.truncate(matched_count);
+        }
+        let separated = unmatched_mode == UnmatchedMode::Separated
+            && matched_count > 0
+            && matched_count < shown.len();
+        if separated {
+            ui.weak(format!("{} of {} match", matched_count, shown.len()));
+        }
+
+        let expanded_id = ui.id().with("expanded");
+        let expanded = ui.data(|d| d.get_temp(expanded_id)).unwrap_or(false);
+        let max_commands = Plugin::get().config().max_commands_per_module;
+        let limit = if max_commands == 0 || expanded {
+            shown.len()
+        } else {
+            max_commands.min(shown.len())
+        };
+
+        let inline = Plugin::get().config().inline_descriptions;
+        for (i, &(cmd, description, matched)) in shown[..limit].iter().enumerate() {
+            if separated && i == matched_count {
+                ui.separator();
             }
+            let label = Self::draw_command(ui, input, cmd, matched, registered);
+            if inline && !description.is_empty() {
+                Self::draw_inline_description(ui, cmd, description);
+            }
+
+            label.on_hover_ui(|ui| {
+                if !inline && !description.is_empty() {
+                    ui.label(description);
+                }
+                if let Some(note) = Plugin::get().note_of(cmd) {
+                    ui.label(RichText::new(note).italics());
+                }
+                ui.weak(source);
+                if let Some(handler) = Self::handler_text(cmd) {
+                    ui.weak(handler);
+                }
+            });
+        }
+
+        if limit < shown.len()
+            && ui
+                .small_button(format!("+{} more", shown.len() - limit))
+                .clicked()
+        {
+            ui.data_mut(|d| d.insert_temp(expanded_id, true));
+        }
+    }
+
+    fn draw_inline_description(ui: &mut egui::Ui, cmd: &str, description: &str) {
+        ui.indent(cmd, |ui| {
+            ui.label(RichText::new(description).weak().small());
         });
     }
+
+    // Helps to find out what has registered a command of an unknown module.
+    fn handler_text(cmd: &str) -> Option<String> {
+        let plugin = Plugin::get();
+        if !plugin.config().show_handler_addresses {
+            return None;
+        }
+        let address = plugin.samp_handler_address(cmd)?;
+        Some(match plugin.samp_handler_location(cmd) {
+            Some((module, offset)) => {
+                format!("Handler: {:#X} ({}+{:#X})", address, module, offset)
+            }
+            None => format!("Handler: {:#X}", address),
+        })
+    }
+
+    fn draw_unified_commands(
+        &mut self,
+        ui: &mut egui::Ui,
+        chat_input: &String,
+        input: &mut samp::Input,
+    ) {
+        let alphabetical = Plugin::get().config().view_mode == ViewMode::Alphabetical;
+        let max_height = self
+            .max_list_height
+            .unwrap_or_else(|| ui.input(|i| i.screen_rect.height()) - ui.cursor().top() - 100.);
+
+        let commands = Plugin::get().commands();
+        let entries = commands
+            .entries()
+            .into_iter()
+            .filter(|entry| Self::is_command_shown(entry.command));
+
+        egui::ScrollArea::vertical()
+            .id_source("unified")
+            .max_height(max_height)
+            .show(ui, |ui| {
+                ui.set_min_width(ui.available_width());
+                if !alphabetical {
+                    for entry in entries {
+                        Self::draw_unified_entry(ui, input, chat_input, &entry);
+                    }
+                    return;
+                }
+
+                let mut letters: BTreeMap<char, Vec<CommandEntry>> = BTreeMap::new();
+                for entry in entries {
+                    letters
+                        .entry(Self::first_letter(entry.command))
+                        .or_default()
+                        .push(entry);
+                }
+                for (letter, entries) in letters {
+                    egui::CollapsingHeader::new(letter.to_string())
+                        .id_source(("letter", letter))
+                        .default_open(true)
+                        .show(ui, |ui| {
+                            for entry in &entries {
+                                Self::draw_unified_entry(ui, input, chat_input, entry);
+                            }
+                        });
+                }
+            });
+    }
+
+    // Letters are grouped regardless of case, the rest go under '#'.
+    fn first_letter(cmd: &str) -> char {
+        match cmd.strip_prefix(CMD_PREFIX).unwrap_or(cmd).chars().next() {
+            Some(c) if c.is_alphabetic() => c.to_uppercase().next().unwrap_or(c),
+            _ => '#',
+        }
+    }
+
+    fn draw_unified_entry(
+        ui: &mut egui::Ui,
+        input: &mut samp::Input,
+        chat_input: &str,
+        entry: &CommandEntry,
+    ) {
+        let config = Plugin::get().config();
+        let inline = config.inline_descriptions;
+        let query = if config.unfiltered_categories.contains(entry.category) {
+            ""
+        } else {
+            chat_input
+        };
+        let matched = match_command(query, entry.command, config.match_mode).is_some();
+        ui.horizontal(|ui| {
+            let label = Self::draw_command(ui, input, entry.command, matched, entry.is_registered);
+            label.on_hover_ui(|ui| {
+                if !inline && !entry.description.is_empty() {
+                    ui.label(entry.description);
+                }
+                if let Some(note) = Plugin::get().note_of(entry.command) {
+                    ui.label(RichText::new(note).italics());
+                }
+                ui.weak(format!("{}: {}", entry.source, entry.module));
+                if let Some(handler) = Self::handler_text(entry.command) {
+                    ui.weak(handler);
+                }
+            });
+
+            if config.show_source {
+                ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                    ui.weak(entry.category);
+                });
+            }
+        });
+        if inline && !entry.description.is_empty() {
+            Self::draw_inline_description(ui, entry.command, entry.description);
+        }
+    }
+
+    fn flash_id(cmd: &str) -> egui::Id {
+        egui::Id::new("insert_flash").with(cmd)
+    }
+
+    // Briefly highlights the command as a confirmation that it has been inserted.
+    pub fn flash_inserted(ctx: &egui::Context, cmd: &str) {
+        if Plugin::get().config().insert_feedback {
+            ctx.animate_value_with_time(Self::flash_id(cmd), 1.0, 0.0);
+        }
+    }
+
+    fn is_command_shown(cmd: &str) -> bool {
+        let config = Plugin::get().config();
+        config.show_hidden || !config.hidden_commands.contains(cmd)
+    }
+
+    // Clicking a command puts it into the chat input.
+    // Matching commands can also be selected with the keyboard.
+    fn draw_command(
+        ui: &mut egui::Ui,
+        input: &mut samp::Input,
+        cmd: &str,
+        matched: bool,
+        registered: bool,
+    ) -> egui::Response {
+        let plugin = Plugin::get();
+        let config = plugin.config();
+        let hidden = config.hidden_commands.contains(cmd);
+        // Commands nothing has registered would most likely not work.
+        let insertable = registered || config.insert_documented;
+        let index = plugin.selectable_command_count();
+        let selectable = matched && !hidden && insertable;
+        let selected = selectable && Plugin::get().add_selectable_command(cmd);
+
+        // Only the displayed text, the registered form is inserted.
+        let name = plugin.display_name(cmd);
+        let mut display = if config.show_prefix {
+            name
+        } else {
+            name.strip_prefix(CMD_PREFIX).unwrap_or(name)
+        }
+        .to_string();
+        if config.quick_insert && selectable && index < QUICK_INSERT_KEYS.len() {
+            display = format!("{} {}", index + 1, display);
+        }
+
+        let flash = if config.insert_feedback {
+            ui.ctx()
+                .animate_value_with_time(Self::flash_id(cmd), 0.0, INSERT_FLASH_TIME)
+        } else {
+            0.0
+        };
+
+        let fresh = ui.ctx().animate_bool_with_time(
+            egui::Id::new("recently_registered").with(cmd),
+            plugin.is_recently_registered(cmd),
+            REGISTERED_FADE_TIME,
+        );
+
+        let mut text = if selected {
+            RichText::new(display).background_color(ui.visuals().selection.bg_fill)
+        } else if flash > 0.0 {
+            RichText::new(display)
+                .background_color(ui.visuals().selection.bg_fill.gamma_multiply(flash))
+        } else if fresh > 0.0 {
+            RichText::new(display)
+                .background_color(ui.visuals().warn_fg_color.gamma_multiply(0.4 * fresh))
+        } else if matched {
+            RichText::new(display)
+        } else {
+            RichText::new(display).weak()
+        };
+        if hidden {
+            text = text.strikethrough();
+        }
+        if !registered {
+            text = text.italics();
+        }
+
+        ui.horizontal(|ui| {
+            let label = ui.add(Label::new(text).sense(Sense::click()));
+
+            let label = label.context_menu(|ui| {
+                let text = if plugin.note_of(cmd).is_some() {
+                    "Edit note"
+                } else {
+                    "Add note"
+                };
+                if ui.button(text).clicked() {
+                    Self::request_note_edit(ui.ctx(), cmd);
+                    ui.close_menu();
+                }
+
+                let mut confirm = plugin.needs_confirmation(cmd);
+                if ui.checkbox(&mut confirm, "Ask before inserting").clicked() {
+                    Plugin::get().toggle_confirm_command(cmd);
+                    ui.close_menu();
+                }
+            });
+
+            if label.clicked() && insertable {
+                if plugin.needs_confirmation(cmd) {
+                    Self::request_confirmation(ui.ctx(), cmd);
+                } else if (input.enabled.as_bool() || input.open())
+                    && Plugin::get().insert_command(input, cmd).is_some()
+                {
+                    Self::flash_inserted(ui.ctx(), cmd);
+                }
+            }
+            if selected {
+                label.scroll_to_me(None);
+            }
+
+            if let Some(sources) = plugin.commands().duplicates.get(cmd) {
+                ui.weak(format!("+{}", sources.join(", ")));
+            }
+
+            // Only on hover, to keep the list clean.
+            if ui.ui_contains_pointer() {
+                let hint = if hidden { "Show" } else { "Hide" };
+                if ui.small_button("👁").on_hover_text(hint).clicked() {
+                    Plugin::get().toggle_hidden_command(cmd);
+                }
+            }
+
+            label
+        })
+        .inner
+    }
+
+    fn draw_copyright(&mut self, ui: &mut egui::Ui) {
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.strong("Copyright © Rinat Namazov").on_hover_ui(|ui| {
+                ui.label(concat!("SA-MP Command Helper v", env!("CARGO_PKG_VERSION")));
+                ui.label("https://rinwares.com");
+            });
+            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                if ui.small_button("Settings").clicked() {
+                    self.settings_open = !self.settings_open;
+                }
+            });
+        });
+    }
+
+    fn draw_settings(&mut self, ctx: &egui::Context, input: &mut samp::Input) {
+        let mut open = self.settings_open;
+        let response = egui::containers::Window::new("Settings")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let plugin = Plugin::get();
+                let config = plugin.config_mut();
+                let mut changed = false;
+
+                egui::Grid::new("settings").num_columns(2).show(ui, |ui| {
+                    ui.label("Server");
+                    ui.label(Plugin::get().server_address().unwrap_or("Not connected"));
+                    ui.end_row();
+
+                    ui.label("Position");
+                    changed |= Self::anchor_combo(ui, "anchor", &mut config.anchor);
+                    ui.end_row();
+
+                    ui.label("Docked").on_hover_text(
+                        "Show the commands in a panel along the edge of the screen instead",
+                    );
+                    egui::ComboBox::from_id_source("dock")
+                        .selected_text(format!("{:?}", config.dock))
+                        .show_ui(ui, |ui| {
+                            for dock in [
+                                Dock::Floating,
+                                Dock::Left,
+                                Dock::Right,
+                                Dock::Top,
+                                Dock::Bottom,
+                            ] {
+                                changed |= ui
+                                    .selectable_value(&mut config.dock, dock, format!("{:?}", dock))
+                                    .changed();
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("");
+                    changed |= ui
+                        .checkbox(&mut config.flip_above_chat, "Above the chat near the bottom")
+                        .on_hover_text("When following the chat and there is more space above it")
+                        .changed();
+                    ui.end_row();
+
+                    ui.label("Recalls position");
+                    changed |= Self::anchor_combo(ui, "recall_anchor", &mut config.recall_anchor);
+                    ui.end_row();
+
+                    changed |= ui
+                        .checkbox(&mut config.pinned, "Pinned position")
+                        .on_hover_text("Show the commands while the chat is closed")
+                        .changed();
+                    changed |= Self::anchor_combo(ui, "pinned_anchor", &mut config.pinned_anchor);
+                    ui.end_row();
+
+                    ui.label("Recalls offset");
+                    ui.horizontal(|ui| {
+                        changed |= ui
+                            .add(egui::DragValue::new(&mut config.recall_offset[0]).prefix("x "))
+                            .changed();
+                        changed |= ui
+                            .add(egui::DragValue::new(&mut config.recall_offset[1]).prefix("y "))
+                            .changed();
+                    });
+                    ui.end_row();
+
+                    ui.label("Margin");
+                    changed |= ui
+                        .add(egui::Slider::new(&mut config.anchor_margin, 0.0..=200.0))
+                        .changed();
+                    ui.end_row();
+
+                    ui.label("Settings command");
+                    changed |= ui
+                        .text_edit_singleline(&mut config.settings_command)
+                        .on_hover_text(
+                            "Typing it in chat opens this window, leave empty to disable",
+                        )
+                        .changed();
+                    ui.end_row();
+
+                    ui.label("Min column width");
+                    changed |= ui
+                        .add(egui::Slider::new(
+                            &mut config.min_column_width,
+                            config::MIN_COLUMN_WIDTH..=config::MAX_COLUMN_WIDTH,
+                        ))
+                        .changed();
+                    ui.end_row();
+
+                    ui.label("Scale");
+                    if ui
+                        .add(
+                            egui::Slider::new(
+                                &mut config.ui_scale,
+                                config::MIN_UI_SCALE..=config::MAX_UI_SCALE,
+                            )
+                            .step_by(0.05),
+                        )
+                        .changed()
+                    {
+                        changed = true;
+                        Plugin::get().invalidate_style();
+                    }
+                    ui.end_row();
+
+                    ui.label("Line spacing");
+                    if ui
+                        .add(egui::Slider::new(
+                            &mut config.item_spacing,
+                            config::MIN_ITEM_SPACING..=config::MAX_ITEM_SPACING,
+                        ))
+                        .changed()
+                    {
+                        changed = true;
+                        Plugin::get().invalidate_style();
+                    }
+                    ui.end_row();
+
+                    ui.label("Window");
+                    ui.horizontal(|ui| {
+                        let mut style_changed =
+                            ui.checkbox(&mut config.window_shadow, "Shadow").changed();
+                        style_changed |= ui.checkbox(&mut config.window_border, "Border").changed();
+                        if config.window_border {
+                            let [r, g, b, a] = config.window_border_color;
+                            let mut color = Color32::from_rgba_unmultiplied(r, g, b, a);
+                            if egui::color_picker::color_edit_button_srgba(
+                                ui,
+                                &mut color,
+                                egui::color_picker::Alpha::OnlyBlend,
+                            )
+                            .changed()
+                            {
+                                config.window_border_color = color.to_srgba_unmultiplied();
+                                style_changed = true;
+                            }
+                        }
+                        if style_changed {
+                            changed = true;
+                            Plugin::get().invalidate_style();
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Auto-hide").on_hover_text(
+                        "Seconds without typing before the list hides, 0 to disable",
+                    );
+                    changed |= ui
+                        .add(
+                            egui::Slider::new(&mut config.auto_hide_seconds, 0.0..=60.0)
+                                .suffix(" s"),
+                        )
+                        .changed();
+                    ui.end_row();
+
+                    ui.label("Min query length").on_hover_text(
+                        "Characters to type after the prefix before the list appears",
+                    );
+                    changed |= ui
+                        .add(egui::DragValue::new(&mut config.min_query_length).clamp_range(0..=8))
+                        .changed();
+                    ui.end_row();
+
+                    ui.label("Max commands per module")
+                        .on_hover_text("The rest are shown on click, 0 to show all");
+                    changed |= ui
+                        .add(egui::DragValue::new(&mut config.max_commands_per_module))
+                        .changed();
+                    ui.end_row();
+
+                    ui.label("Max recalls");
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut config.max_recalls)
+                                .clamp_range(1..=samp::MAX_RECALL_HISTORY),
+                        )
+                        .changed();
+                    ui.end_row();
+
+                    ui.label("View");
+                    egui::ComboBox::from_id_source("view_mode")
+                        .selected_text(format!("{:?}", config.view_mode))
+                        .show_ui(ui, |ui| {
+                            for mode in [
+                                ViewMode::Categories,
+                                ViewMode::Unified,
+                                ViewMode::Alphabetical,
+                            ] {
+                                changed |= ui
+                                    .selectable_value(
+                                        &mut config.view_mode,
+                                        mode,
+                                        format!("{:?}", mode),
+                                    )
+                                    .changed();
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Filtered categories")
+                        .on_hover_text("Unchecked ones always show all their commands");
+                    ui.horizontal_wrapped(|ui| {
+                        for category in Plugin::get().commands().iter().filter(|c| c.is_visible) {
+                            let mut filtered =
+                                !config.unfiltered_categories.contains(&category.name);
+                            if ui.checkbox(&mut filtered, &category.name).changed() {
+                                changed = true;
+                                if filtered {
+                                    config.unfiltered_categories.remove(&category.name);
+                                } else {
+                                    config.unfiltered_categories.insert(category.name.clone());
+                                }
+                            }
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Same commands");
+                    let duplicate_mode = config.duplicate_mode;
+                    egui::ComboBox::from_id_source("duplicate_mode")
+                        .selected_text(format!("{:?}", config.duplicate_mode))
+                        .show_ui(ui, |ui| {
+                            for mode in [
+                                DuplicateMode::Separate,
+                                DuplicateMode::Merge,
+                                DuplicateMode::Prefer,
+                            ] {
+                                ui.selectable_value(
+                                    &mut config.duplicate_mode,
+                                    mode,
+                                    format!("{:?}", mode),
+                                );
+                            }
+                        })
+                        .response
+                        .on_hover_text("How commands registered by several sources are shown");
+                    if config.duplicate_mode != duplicate_mode {
+                        changed = true;
+                        Plugin::get().commands_changed();
+                    }
+                    ui.end_row();
+
+                    ui.label("Matching");
+                    egui::ComboBox::from_id_source("match_mode")
+                        .selected_text(format!("{:?}", config.match_mode))
+                        .show_ui(ui, |ui| {
+                            for mode in [
+                                MatchMode::Prefix,
+                                MatchMode::CaseInsensitivePrefix,
+                                MatchMode::Fuzzy,
+                            ] {
+                                changed |= ui
+                                    .selectable_value(
+                                        &mut config.match_mode,
+                                        mode,
+                                        format!("{:?}", mode),
+                                    )
+                                    .changed();
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Not matching");
+                    egui::ComboBox::from_id_source("unmatched_mode")
+                        .selected_text(format!("{:?}", config.unmatched_mode))
+                        .show_ui(ui, |ui| {
+                            for mode in [
+                                UnmatchedMode::Dimmed,
+                                UnmatchedMode::Separated,
+                                UnmatchedMode::Hidden,
+                            ] {
+                                changed |= ui
+                                    .selectable_value(
+                                        &mut config.unmatched_mode,
+                                        mode,
+                                        format!("{:?}", mode),
+                                    )
+                                    .changed();
+                            }
+                        });
+                    ui.end_row();
+                });
+
+                changed |= ui
+                    .checkbox(
+                        &mut config.show_server_address,
+                        "Show server address above commands",
+                    )
+                    .changed();
+
+                changed |= ui
+                    .checkbox(&mut config.show_prefix, "Show commands with the prefix")
+                    .changed();
+
+                if ui
+                    .checkbox(
+                        &mut config.name_normalization.enabled,
+                        "Clean up command names",
+                    )
+                    .on_hover_text(
+                        "Hide underscores and versions around names, commands are still inserted as registered",
+                    )
+                    .changed()
+                {
+                    changed = true;
+                    Plugin::get().commands_changed();
+                }
+
+                changed |= ui
+                    .checkbox(
+                        &mut config.inline_descriptions,
+                        "Show descriptions under commands",
+                    )
+                    .on_hover_text("Takes more space, otherwise they are shown on hover")
+                    .changed();
+
+                changed |= ui
+                    .checkbox(
+                        &mut config.strip_module_extensions,
+                        "Hide file extensions of modules",
+                    )
+                    .changed();
+
+                changed |= ui
+                    .checkbox(
+                        &mut config.hide_on_focus_loss,
+                        "Hide when the game is inactive",
+                    )
+                    .changed();
+
+                changed |= ui
+                    .checkbox(&mut config.insert_feedback, "Highlight inserted commands")
+                    .changed();
+
+                changed |= ui
+                    .checkbox(&mut config.quick_insert, "Number commands for Alt+1..9")
+                    .changed();
+
+                if ui
+                    .checkbox(&mut config.show_documented, "Show documented commands")
+                    .on_hover_text("Commands that have a description but are not registered")
+                    .changed()
+                {
+                    changed = true;
+                    Plugin::get().refresh_documented();
+                }
+                changed |= ui
+                    .checkbox(
+                        &mut config.insert_documented,
+                        "Allow inserting documented commands",
+                    )
+                    .changed();
+
+                changed |= ui
+                    .checkbox(&mut config.show_hidden, "Show hidden commands")
+                    .on_hover_text(format!("{} hidden", config.hidden_commands.len()))
+                    .changed();
+
+                changed |= ui
+                    .checkbox(
+                        &mut config.show_source,
+                        "Show command source in the unified list",
+                    )
+                    .changed();
+
+                ui.separator();
+
+                changed |= ui
+                    .checkbox(
+                        &mut config.copy_with_headers,
+                        "Include headers when copying",
+                    )
+                    .changed();
+                if ui.button("Copy visible commands").clicked() {
+                    let chat_input = input.edit_box().get_text();
+                    let query = if chat_input.starts_with(CMD_PREFIX) {
+                        command_token(&chat_input)
+                    } else {
+                        ""
+                    };
+                    let text = format_command_list(
+                        Plugin::get().commands(),
+                        query,
+                        config.match_mode,
+                        config.copy_with_headers,
+                    );
+                    if let Err(e) = utils::set_clipboard_text(gta::get_window_handle(), &text) {
+                        eprintln!("utils::set_clipboard_text: {}", e);
+                    }
+                }
+
+                if ui
+                    .checkbox(&mut config.ipc_pipe, "Share commands through a named pipe")
+                    .on_hover_text(r"For external tools, \\.\pipe\samp-cmd-helper")
+                    .changed()
+                {
+                    changed = true;
+                    Plugin::get().apply_ipc_config();
+                }
+
+                if ui
+                    .checkbox(
+                        &mut config.chat_suggestions,
+                        "Suggest commands mentioned in chat",
+                    )
+                    .changed()
+                {
+                    changed = true;
+                    Plugin::get().apply_chat_hook_config();
+                }
+
+                if ui
+                    .checkbox(
+                        &mut config.server_descriptions,
+                        "Take descriptions from server messages",
+                    )
+                    .on_hover_text(
+                        "Messages must match the format, {command} and {description} are replaced",
+                    )
+                    .changed()
+                {
+                    changed = true;
+                    Plugin::get().apply_chat_hook_config();
+                }
+                if config.server_descriptions {
+                    changed |= ui
+                        .text_edit_singleline(&mut config.server_description_format)
+                        .changed();
+                }
+
+                if !config.ignored_modules.is_empty() {
+                    egui::CollapsingHeader::new("Ignored modules").show(ui, |ui| {
+                        for module in config.ignored_modules.clone() {
+                            ui.horizontal(|ui| {
+                                ui.label(&module);
+                                if ui.small_button("Restore").clicked() {
+                                    Plugin::get().toggle_ignored_module(&module);
+                                }
+                            });
+                        }
+                    });
+                }
+
+                egui::CollapsingHeader::new("Inserting").show(ui, |ui| {
+                    changed |= Self::draw_insert_modes(ui, &mut config.insert_modes);
+                });
+
+                egui::CollapsingHeader::new("Keys").show(ui, |ui| {
+                    changed |= Self::draw_key_bindings(ui, &mut config.keys);
+                });
+
+                ui.separator();
+
+                if ui
+                    .button("Refresh commands")
+                    .on_hover_text("After loading scripts or plugins")
+                    .clicked()
+                {
+                    Plugin::get().refresh_commands();
+                }
+
+                let paused = Plugin::get().are_updates_paused();
+                if ui
+                    .button(if paused {
+                        "Resume updates"
+                    } else {
+                        "Pause updates"
+                    })
+                    .on_hover_text("Keep the list as it is while commands are registered")
+                    .clicked()
+                {
+                    Plugin::get().set_updates_paused(!paused);
+                }
+
+                if ui.button("Show tutorial").clicked() {
+                    config.tutorial_shown = false;
+                }
+
+                if ui.button("Diagnostics").clicked() {
+                    self.diagnostics_open = !self.diagnostics_open;
+                }
+
+                if changed {
+                    plugin.save_config();
+                }
+            });
+        self.settings_open = open;
+
+        if let Some(response) = response {
+            Self::track_overlay_rect(ctx, response.response.rect);
+        }
+    }
+
+    fn anchor_combo(ui: &mut egui::Ui, id: &str, value: &mut Anchor) -> bool {
+        let mut changed = false;
+        egui::ComboBox::from_id_source(id)
+            .selected_text(format!("{:?}", value))
+            .show_ui(ui, |ui| {
+                for anchor in [
+                    Anchor::FollowChat,
+                    Anchor::TopLeft,
+                    Anchor::TopRight,
+                    Anchor::BottomLeft,
+                    Anchor::BottomRight,
+                ] {
+                    changed |= ui
+                        .selectable_value(value, anchor, format!("{:?}", anchor))
+                        .changed();
+                }
+            });
+        changed
+    }
+
+    // Returns true if a mode has been changed.
+    fn draw_insert_modes(
+        ui: &mut egui::Ui,
+        modes: &mut HashMap<CategoryKey, config::InsertMode>,
+    ) -> bool {
+        let mut changed = false;
+        egui::Grid::new("insert_modes").show(ui, |ui| {
+            // By where the commands come from, wherever they are shown.
+            for (key, category) in Plugin::get().source_commands().keyed() {
+                if !key.is_builtin() || !category.is_registered || category.modules.is_empty() {
+                    continue;
+                }
+                let mut mode = modes.get(&key).copied().unwrap_or_default();
+                ui.label(&category.name);
+                let mut mode_changed = ui
+                    .checkbox(&mut mode.prefix, "Prefix")
+                    .on_hover_text("Insert the commands with the prefix")
+                    .changed();
+                mode_changed |= ui
+                    .checkbox(&mut mode.send, "Send")
+                    .on_hover_text("Send the commands right away")
+                    .changed();
+                if mode_changed {
+                    changed = true;
+                    if mode == config::InsertMode::default() {
+                        modes.remove(&key);
+                    } else {
+                        modes.insert(key, mode);
+                    }
+                }
+                ui.end_row();
+            }
+        });
+        changed
+    }
+
+    // Returns true if a binding has been changed.
+    fn draw_key_bindings(ui: &mut egui::Ui, bindings: &mut config::KeyBindings) -> bool {
+        let mut changed = false;
+        let mut bound = Vec::new();
+
+        egui::Grid::new("key_bindings").show(ui, |ui| {
+            for (action, binding) in bindings.iter_mut() {
+                ui.label(action);
+                let current = keys::parse(binding);
+                egui::ComboBox::from_id_source(action)
+                    .selected_text(current.map_or("None", |key| key.name()))
+                    .show_ui(ui, |ui| {
+                        if ui.selectable_label(current.is_none(), "None").clicked() {
+                            binding.clear();
+                            changed = true;
+                        }
+                        for key in keys::bindable_keys() {
+                            if ui
+                                .selectable_label(current == Some(key), key.name())
+                                .clicked()
+                            {
+                                *binding = key.name().to_string();
+                                changed = true;
+                            }
+                        }
+                    });
+                ui.end_row();
+
+                if let Some(key) = keys::parse(binding) {
+                    bound.push(key);
+                }
+            }
+        });
+
+        // Conflicts are allowed, the user may know better, but should be aware of them.
+        for (i, key) in bound.iter().enumerate() {
+            if bound[..i].contains(key) {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!("{} is bound more than once", key.name()),
+                );
+            }
+            if keys::SUPPRESSION_KEYS.contains(key) {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!("The overlay is hidden while {} is held", key.name()),
+                );
+            } else if keys::SAMP_KEYS.contains(key) {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    format!("{} is also used by SA-MP", key.name()),
+                );
+            }
+        }
+
+        changed
+    }
+
+    fn draw_recalls(&self, ui: &mut egui::Ui, input: &mut samp::Input) {
+        ui.vertical_centered(|ui| {
+            ui.strong("Recalls");
+        });
+
+        ui.indent(ui.id(), |ui| {
+            // The latest recall is the first one.
+            let count = (input.total_recall.max(0) as usize)
+                .min(samp::MAX_RECALL_HISTORY)
+                .min(Plugin::get().config().max_recalls);
+            for i in 0..count {
+                let text = utils::decode_ansi(&input.recall_buffer[i]);
+                if text.is_empty() {
+                    continue;
+                }
+
+                let text = if input.current_recall == -1 || i == input.current_recall as usize {
+                    RichText::new(text)
+                } else {
+                    RichText::new(text).weak()
+                };
+
+                let label = ui.add(Label::new(text).sense(Sense::click()));
+
+                if label.clicked() {
+                    input.current_recall = i as i32;
+                    // The original bytes, to keep the encoding as is.
+                    let recall = input.recall_buffer[i].as_ptr() as *const c_char;
+                    input.edit_box().set_text_raw(recall);
+                }
+            }
+        });
+    }
+
+    fn draw_tutorial(ctx: &egui::Context) {
+        let plugin = Plugin::get();
+        let config = plugin.config();
+        let key = |name: &str| keys::parse(name).map_or("-", |key| key.name());
+
+        let mut open = true;
+        let mut got_it = false;
+        let response = egui::containers::Window::new("Welcome to samp-cmd-helper")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(Align2::CENTER_CENTER, vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                ui.label("Open the chat and type / to see the commands of the server, scripts and plugins.");
+                ui.label("Keep typing to filter them, click a command to insert it.");
+                ui.label(format!(
+                    "Ctrl+{} and Ctrl+{} select a command, {} inserts it, {} completes the common part, {} hides the list.",
+                    key(&config.keys.select_previous),
+                    key(&config.keys.select_next),
+                    key(&config.keys.insert),
+                    key(&config.keys.complete),
+                    key(&config.keys.dismiss),
+                ));
+                if !config.settings_command.trim().is_empty() {
+                    ui.label(format!(
+                        "Send {} to open the settings, where this window can be shown again.",
+                        config.settings_command.trim()
+                    ));
+                }
+                ui.vertical_centered(|ui| {
+                    got_it = ui.button("Got it").clicked();
+                });
+            });
+
+        if let Some(response) = response {
+            Self::track_overlay_rect(ctx, response.response.rect);
+        }
+
+        if !open || got_it {
+            plugin.config_mut().tutorial_shown = true;
+            plugin.save_config();
+        }
+    }
+
+    // Commands are drawn without access to the Ui, so the request goes through egui memory.
+    fn request_note_edit(ctx: &egui::Context, cmd: &str) {
+        ctx.data_mut(|d| d.insert_temp(egui::Id::new("note_request"), cmd.to_string()));
+    }
+
+    fn take_note_request(ctx: &egui::Context) -> Option<String> {
+        let id = egui::Id::new("note_request");
+        ctx.data_mut(|d| {
+            let cmd = d.get_temp::<String>(id);
+            d.remove::<String>(id);
+            cmd
+        })
+    }
+
+    pub fn request_confirmation(ctx: &egui::Context, cmd: &str) {
+        ctx.data_mut(|d| d.insert_temp(egui::Id::new("confirmation_request"), cmd.to_string()));
+    }
+
+    fn take_confirmation_request(ctx: &egui::Context) -> Option<String> {
+        let id = egui::Id::new("confirmation_request");
+        ctx.data_mut(|d| {
+            let cmd = d.get_temp::<String>(id);
+            d.remove::<String>(id);
+            cmd
+        })
+    }
+
+    fn draw_confirmation(&mut self, ctx: &egui::Context, input: &mut samp::Input) {
+        let Some(cmd) = &self.confirmation else {
+            return;
+        };
+
+        let mut open = true;
+        let mut close = false;
+        let response = egui::containers::Window::new("Confirm")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(Align2::CENTER_CENTER, vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                ui.label(format!("Insert {}?", cmd));
+                ui.horizontal(|ui| {
+                    if ui.button("Insert").clicked() {
+                        // The pinned list is shown with the chat closed.
+                        if (input.enabled.as_bool() || input.open())
+                            && Plugin::get().insert_command(input, cmd).is_some()
+                        {
+                            Self::flash_inserted(ui.ctx(), cmd);
+                        }
+                        close = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        close = true;
+                    }
+                });
+            });
+        if !open || close {
+            self.confirmation = None;
+        }
+
+        if let Some(response) = response {
+            Self::track_overlay_rect(ctx, response.response.rect);
+        }
+    }
+
+    fn draw_note_editor(&mut self, ctx: &egui::Context) {
+        let Some((cmd, note)) = &mut self.note_editor else {
+            return;
+        };
+
+        let mut open = true;
+        let mut close = false;
+        let response = egui::containers::Window::new("Note")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.strong(cmd.as_str());
+                ui.add(egui::TextEdit::multiline(note).desired_rows(3));
+                ui.horizontal(|ui| {
+                    if ui.button("Save").clicked() {
+                        Plugin::get().set_note(cmd, note);
+                        close = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        close = true;
+                    }
+                });
+            });
+        if !open || close {
+            self.note_editor = None;
+        }
+
+        if let Some(response) = response {
+            Self::track_overlay_rect(ctx, response.response.rect);
+        }
+    }
+
+    // Commands registered twice, e.g. by two scripts, may not do what their author expects.
+    fn draw_conflicts(&mut self, ui: &mut egui::Ui) {
+        let revision = Plugin::get().filter_revision();
+        if !matches!(self.conflicts, Some((r, _)) if r == revision) {
+            let conflicts = Plugin::get().source_commands().conflicts();
+            self.conflicts = Some((revision, conflicts));
+        }
+        let Some((_, conflicts)) = &self.conflicts else {
+            return;
+        };
+
+        egui::CollapsingHeader::new(format!("Conflicting commands ({})", conflicts.len()))
+            .id_source("conflicts")
+            .show(ui, |ui| {
+                if conflicts.is_empty() {
+                    ui.weak("No command is registered twice");
+                    return;
+                }
+                egui::ScrollArea::vertical()
+                    .max_height(200.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("conflicts").num_columns(2).show(ui, |ui| {
+                            for conflict in conflicts {
+                                ui.strong(&conflict.command);
+                                ui.vertical(|ui| {
+                                    for owner in &conflict.owners {
+                                        ui.label(owner);
+                                    }
+                                });
+                                ui.end_row();
+                            }
+                        });
+                    });
+            });
+    }
+
+    fn draw_diagnostics(&mut self, ctx: &egui::Context) {
+        fn status(ui: &mut egui::Ui, name: &str, ok: bool) {
+            ui.label(name);
+            if ok {
+                ui.colored_label(Color32::GREEN, "OK");
+            } else {
+                ui.colored_label(Color32::RED, "Failed");
+            }
+            ui.end_row();
+        }
+
+        let mut open = self.diagnostics_open;
+        let response = egui::containers::Window::new("Diagnostics")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let plugin = Plugin::get();
+                let hooks = plugin.hook_status();
+
+                egui::Grid::new("diagnostics")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label("SA-MP");
+                        ui.label(format!(
+                            "{:?} at {:#X}",
+                            plugin.samp_version(),
+                            plugin.samp_base_address()
+                        ));
+                        ui.end_row();
+
+                        ui.label("SAMPFUNCS");
+                        ui.label(if sampfuncs::is_initialized() {
+                            "Loaded"
+                        } else if sampfuncs::has_failed() {
+                            "Failed, see the log"
+                        } else {
+                            "Not installed"
+                        });
+                        ui.end_row();
+
+                        ui.label("MoonLoader");
+                        ui.label(if moonloader::is_initialized() {
+                            "Loaded"
+                        } else if moonloader::has_failed() {
+                            "Failed, see the log"
+                        } else {
+                            "Not installed"
+                        });
+                        ui.end_row();
+
+                        ui.label("Command updates");
+                        if plugin.are_updates_paused() {
+                            ui.colored_label(ui.visuals().warn_fg_color, "Paused");
+                        } else {
+                            ui.label("Live");
+                        }
+                        ui.end_row();
+
+                        status(ui, "Game loop hook", hooks.defined_state);
+                        status(ui, "Window procedure hook", hooks.wnd_proc);
+                        status(ui, "D3D9 Reset hook", hooks.d3d9_reset);
+                        status(ui, "D3D9 Present hook", hooks.d3d9_present);
+                        status(ui, "User interface", hooks.ui);
+                    });
+
+                self.draw_conflicts(ui);
+
+                let config = plugin.config_mut();
+                if ui
+                    .checkbox(
+                        &mut config.show_handler_addresses,
+                        "Show handler addresses of SA-MP commands",
+                    )
+                    .changed()
+                {
+                    plugin.save_config();
+                }
+            });
+        self.diagnostics_open = open;
+
+        if let Some(response) = response {
+            Self::track_overlay_rect(ctx, response.response.rect);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The chat is open with a command typed, so the list is shown.
+    fn conditions() -> RenderConditions {
+        RenderConditions {
+            menu_open: false,
+            unfocused: false,
+            suppressed: false,
+            chat_open: true,
+            pinned: false,
+            is_command: true,
+            query_length: 1,
+            min_query_length: 0,
+            idle: false,
+            list_dismissed: false,
+            overlay_hidden: false,
+            input_dismissed: false,
+            has_recalls: false,
+            commands_ready: true,
+            has_commands: true,
+        }
+    }
+
+    // Every family must name only fonts that have data, otherwise egui panics.
+    fn assert_usable(fonts: &FontDefinitions) {
+        for family in [FontFamily::Proportional, FontFamily::Monospace] {
+            let names = &fonts.families[&family];
+            assert!(!names.is_empty());
+            assert!(names.iter().all(|name| fonts.font_data.contains_key(name)));
+        }
+    }
+
+    #[test]
+    fn fonts_without_the_main_one_are_usable() {
+        let fonts = Ui::font_definitions(None, Vec::new());
+        assert_usable(&fonts);
+        assert!(!fonts.font_data.contains_key(MAIN_FONT_NAME));
+
+        let ctx = egui::Context::default();
+        ctx.set_fonts(fonts);
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| ui.label("/help"));
+        });
+    }
+
+    #[test]
+    fn additional_fonts_follow_the_main_one() {
+        let font = || FontData::from_static(&[]);
+        let fonts = Ui::font_definitions(Some(font()), vec![("cjk.ttf".to_string(), font())]);
+        assert_usable(&fonts);
+        let proportional = &fonts.families[&FontFamily::Proportional];
+        assert_eq!(proportional[0], MAIN_FONT_NAME);
+        assert_eq!(proportional[1], "cjk.ttf");
+    }
+
+    #[test]
+    fn shows_commands_while_typing() {
+        assert_eq!(should_render(&conditions()), ShouldRender::ShowCommands);
+    }
+
+    #[test]
+    fn visibility_follows_the_decision() {
+        assert!(should_render(&conditions()).is_visible());
+        assert!(ShouldRender::ShowRecalls.is_visible());
+        assert!(ShouldRender::ShowPinned.is_visible());
+
+        let mut c = conditions();
+        c.menu_open = true;
+        assert!(!should_render(&c).is_visible());
+    }
+
+    #[test]
+    fn blocking_screens_hide_everything() {
+        let blockers: [fn(&mut RenderConditions); 3] = [
+            |c| c.menu_open = true,
+            |c| c.unfocused = true,
+            |c| c.suppressed = true,
+        ];
+        for block in blockers {
+            let mut c = conditions();
+            block(&mut c);
+            assert!(c.is_blocked());
+            assert_eq!(should_render(&c), ShouldRender::Hidden);
+
+            c.chat_open = false;
+            c.pinned = true;
+            assert_eq!(should_render(&c), ShouldRender::Hidden);
+        }
+    }
+
+    #[test]
+    fn closed_chat_shows_only_pinned() {
+        let mut c = conditions();
+        c.chat_open = false;
+        assert_eq!(should_render(&c), ShouldRender::Hidden);
+
+        c.pinned = true;
+        assert_eq!(should_render(&c), ShouldRender::ShowPinned);
+
+        c.commands_ready = false;
+        assert_eq!(should_render(&c), ShouldRender::Hidden);
+
+        c.commands_ready = true;
+        c.overlay_hidden = true;
+        assert_eq!(should_render(&c), ShouldRender::Hidden);
+    }
+
+    #[test]
+    fn dismissed_or_idle_list_is_hidden() {
+        let mut c = conditions();
+        c.idle = true;
+        assert_eq!(should_render(&c), ShouldRender::Hidden);
+
+        let mut c = conditions();
+        c.list_dismissed = true;
+        assert_eq!(should_render(&c), ShouldRender::Hidden);
+
+        let mut c = conditions();
+        c.input_dismissed = true;
+        assert_eq!(should_render(&c), ShouldRender::Hidden);
+    }
+
+    #[test]
+    fn short_query_or_no_commands_are_hidden() {
+        let mut c = conditions();
+        c.min_query_length = 2;
+        assert_eq!(should_render(&c), ShouldRender::Hidden);
+        c.query_length = 2;
+        assert_eq!(should_render(&c), ShouldRender::ShowCommands);
+
+        let mut c = conditions();
+        c.has_commands = false;
+        assert_eq!(should_render(&c), ShouldRender::Hidden);
+    }
+
+    #[test]
+    fn recalls_without_command() {
+        let mut c = conditions();
+        c.is_command = false;
+        assert_eq!(should_render(&c), ShouldRender::Hidden);
+
+        c.has_recalls = true;
+        assert_eq!(should_render(&c), ShouldRender::ShowRecalls);
+
+        // Recalls don't wait for the commands.
+        c.commands_ready = false;
+        assert_eq!(should_render(&c), ShouldRender::ShowRecalls);
+    }
 }
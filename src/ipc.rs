@@ -0,0 +1,124 @@
+/*****************************************************************************
+ *
+ *  PROJECT:        samp-cmd-helper
+ *  LICENSE:        See LICENSE in the top level directory
+ *  FILE:           ipc.rs
+ *  DESCRIPTION:    Named pipe for external tools
+ *  COPYRIGHT:      (c) 2024 RINWARES <rinwares.com>
+ *  AUTHOR:         Rinat Namazov <rinat.namazov@rinwares.com>
+ *
+ *****************************************************************************/
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use windows::{
+    core::w,
+    Win32::{
+        Foundation::{CloseHandle, ERROR_PIPE_CONNECTED},
+        Storage::FileSystem::{FlushFileBuffers, WriteFile, PIPE_ACCESS_OUTBOUND},
+        System::Pipes::{
+            ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe, PIPE_REJECT_REMOTE_CLIENTS,
+            PIPE_TYPE_BYTE, PIPE_WAIT,
+        },
+    },
+};
+
+use crate::cmd_storage::Categories;
+
+// The listener thread must not touch the plugin, it only reads this copy.
+static SNAPSHOT: Mutex<String> = Mutex::new(String::new());
+static STARTED: AtomicBool = AtomicBool::new(false);
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// One JSON object per line for every command.
+pub fn update_snapshot(categories: &Categories) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let mut text = String::new();
+    for category in categories.iter().filter(|c| c.is_visible) {
+        for (module, commands) in &category.modules {
+            for (cmd, description) in commands {
+                let line = serde_json::json!({
+                    "category": category.name,
+                    "module": module,
+                    "command": cmd,
+                    "description": description,
+                });
+                text.push_str(&line.to_string());
+                text.push('\n');
+            }
+        }
+    }
+
+    if let Ok(mut snapshot) = SNAPSHOT.lock() {
+        *snapshot = text;
+    }
+}
+
+/// Starts the listener once; disabling only stops serving the commands.
+pub fn set_enabled(enabled: bool, categories: &Categories) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+    if !enabled {
+        return;
+    }
+
+    update_snapshot(categories);
+
+    if !STARTED.swap(true, Ordering::Relaxed) {
+        std::thread::spawn(listen);
+    }
+}
+
+// Every client connecting to \\.\pipe\samp-cmd-helper receives the current list and is disconnected.
+fn listen() {
+    loop {
+        let pipe = unsafe {
+            CreateNamedPipeW(
+                w!(r"\\.\pipe\samp-cmd-helper"),
+                PIPE_ACCESS_OUTBOUND,
+                PIPE_TYPE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                1,
+                64 * 1024,
+                0,
+                0,
+                None,
+            )
+        };
+        if pipe.is_invalid() {
+            eprintln!(
+                "ipc::listen: CreateNamedPipeW: {}",
+                windows::core::Error::from_win32()
+            );
+            return;
+        }
+
+        let connected = match unsafe { ConnectNamedPipe(pipe, None) } {
+            Ok(()) => true,
+            Err(e) => e.code() == ERROR_PIPE_CONNECTED.to_hresult(),
+        };
+
+        if connected && ENABLED.load(Ordering::Relaxed) {
+            let snapshot = match SNAPSHOT.lock() {
+                Ok(snapshot) => snapshot.clone(),
+                Err(_) => String::new(),
+            };
+
+            unsafe {
+                let mut written = 0;
+                if let Err(e) = WriteFile(pipe, Some(snapshot.as_bytes()), Some(&mut written), None)
+                {
+                    eprintln!("ipc::listen: WriteFile: {}", e);
+                }
+                let _ = FlushFileBuffers(pipe);
+            }
+        }
+
+        unsafe {
+            let _ = DisconnectNamedPipe(pipe);
+            let _ = CloseHandle(pipe);
+        }
+    }
+}
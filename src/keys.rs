@@ -0,0 +1,92 @@
+/*****************************************************************************
+ *
+ *  PROJECT:        samp-cmd-helper
+ *  LICENSE:        See LICENSE in the top level directory
+ *  FILE:           keys.rs
+ *  DESCRIPTION:    Key bindings
+ *  COPYRIGHT:      (c) 2024 RINWARES <rinwares.com>
+ *  AUTHOR:         Rinat Namazov <rinat.namazov@rinwares.com>
+ *
+ *****************************************************************************/
+
+use egui::Key;
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    VK_BACK, VK_DELETE, VK_DOWN, VK_END, VK_ESCAPE, VK_F1, VK_HOME, VK_INSERT, VK_LEFT, VK_NEXT,
+    VK_PRIOR, VK_RETURN, VK_RIGHT, VK_SPACE, VK_TAB, VK_UP,
+};
+
+/// Keys SA-MP uses itself, e.g. F8 takes a screenshot.
+pub const SAMP_KEYS: [Key; 6] = [Key::F5, Key::F6, Key::F7, Key::F8, Key::F9, Key::F10];
+
+/// While one of them is held SA-MP draws its own windows over the chat, so the overlay is hidden.
+pub const SUPPRESSION_KEYS: [Key; 2] = [Key::F5, Key::F10];
+
+/// Keys that type a character, bound to an action they couldn't be typed into the chat.
+pub fn is_printable(key: Key) -> bool {
+    let name = key.name();
+    key == Key::Space || (name.len() == 1 && name.as_bytes()[0].is_ascii_alphanumeric())
+}
+
+/// Keys that can be bound, the rest have no stable virtual key code or are typed into the chat.
+pub fn bindable_keys() -> impl Iterator<Item = Key> {
+    Key::ALL
+        .iter()
+        .copied()
+        .filter(|&key| virtual_key(key).is_some() && !is_printable(key))
+}
+
+/// Empty, unknown or printable names mean that nothing is bound.
+pub fn parse(name: &str) -> Option<Key> {
+    Key::from_name(name.trim()).filter(|&key| !is_printable(key))
+}
+
+/// Virtual key code of the key, as received in WM_KEYDOWN.
+pub fn virtual_key(key: Key) -> Option<u16> {
+    let code = match key {
+        Key::ArrowDown => VK_DOWN.0,
+        Key::ArrowLeft => VK_LEFT.0,
+        Key::ArrowRight => VK_RIGHT.0,
+        Key::ArrowUp => VK_UP.0,
+        Key::Escape => VK_ESCAPE.0,
+        Key::Tab => VK_TAB.0,
+        Key::Backspace => VK_BACK.0,
+        Key::Enter => VK_RETURN.0,
+        Key::Space => VK_SPACE.0,
+        Key::Insert => VK_INSERT.0,
+        Key::Delete => VK_DELETE.0,
+        Key::Home => VK_HOME.0,
+        Key::End => VK_END.0,
+        Key::PageUp => VK_PRIOR.0,
+        Key::PageDown => VK_NEXT.0,
+        _ => {
+            let name = key.name();
+            // Letters and digits have their ASCII codes, F1..F12 follow each other.
+            if let Some(n) = name.strip_prefix('F').and_then(|n| n.parse::<u16>().ok()) {
+                if !(1..=12).contains(&n) {
+                    return None;
+                }
+                VK_F1.0 + n - 1
+            } else if name.len() == 1 && name.as_bytes()[0].is_ascii_alphanumeric() {
+                name.as_bytes()[0].to_ascii_uppercase() as u16
+            } else {
+                return None;
+            }
+        }
+    };
+    Some(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn printable_keys_cant_be_bound() {
+        assert_eq!(parse("A"), None);
+        assert_eq!(parse("5"), None);
+        assert_eq!(parse("Space"), None);
+        assert_eq!(parse("F2"), Some(Key::F2));
+        assert!(bindable_keys().all(|key| !is_printable(key)));
+        assert!(bindable_keys().any(|key| key == Key::Tab));
+    }
+}
@@ -3,12 +3,14 @@
  *  PROJECT:        samp-cmd-helper
  *  LICENSE:        See LICENSE in the top level directory
  *  FILE:           lib.rs
- *  DESCRIPTION:    DllMain
+ *  DESCRIPTION:    DllMain and exports
  *  COPYRIGHT:      (c) 2023-2024 RINWARES <rinwares.com>
  *  AUTHOR:         Rinat Namazov <rinat.namazov@rinwares.com>
  *
  *****************************************************************************/
 
+use std::ffi::{c_char, CStr};
+
 #[cfg(debug_assertions)]
 use windows::Win32::System::Console::AllocConsole;
 use windows::Win32::{
@@ -17,10 +19,13 @@ use windows::Win32::{
 };
 
 mod cmd_storage;
+mod config;
 mod cppstd;
 mod errors;
 mod gta;
 mod gui;
+mod ipc;
+mod keys;
 mod moonloader;
 mod plugin;
 mod samp;
@@ -34,7 +39,7 @@ extern "stdcall" fn DllMain(instance: HMODULE, reason: u32, _reserved: *mut ())
             #[cfg(debug_assertions)]
             AllocConsole().unwrap();
 
-            if let Err(e) = plugin::initialize() {
+            if let Err(e) = plugin::initialize(instance) {
                 eprintln!("plugin::initialize: {}", e);
                 return FALSE;
             }
@@ -48,3 +53,76 @@ extern "stdcall" fn DllMain(instance: HMODULE, reason: u32, _reserved: *mut ())
     }
     TRUE
 }
+
+/// Bit flags of successfully installed hooks, see `plugin::HookStatus::bits`.
+#[no_mangle]
+pub extern "C" fn samp_cmd_helper_get_hook_status() -> u32 {
+    match plugin::Plugin::try_get() {
+        Some(plugin) => plugin.hook_status().bits(),
+        None => 0,
+    }
+}
+
+/// Whether the overlay has been drawn in the last frame, e.g. to avoid drawing over it.
+#[no_mangle]
+pub extern "C" fn samp_cmd_helper_is_overlay_visible() -> bool {
+    gui::is_overlay_visible()
+}
+
+/// Shows the commands of another plugin as a category named `name`, can be called from any thread.
+/// Strings are UTF-8, commands may be given with or without the prefix.
+/// Calling it again with the same name replaces the commands, `count` of 0 removes them.
+///
+/// # Safety
+///
+/// `name` and `count` elements of `commands` must be valid null-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn samp_cmd_helper_register_command_source(
+    name: *const c_char,
+    commands: *const *const c_char,
+    count: usize,
+) -> bool {
+    if name.is_null() || (commands.is_null() && count != 0) {
+        return false;
+    }
+    let name = match CStr::from_ptr(name).to_str() {
+        Ok(name) if !name.is_empty() => name.to_string(),
+        _ => return false,
+    };
+
+    let mut list = Vec::with_capacity(count);
+    for i in 0..count {
+        let command = *commands.add(i);
+        if command.is_null() {
+            return false;
+        }
+        match CStr::from_ptr(command).to_str() {
+            Ok(command) => list.push(command.to_string()),
+            Err(_) => return false,
+        }
+    }
+
+    plugin::register_command_source(name, list);
+    true
+}
+
+/// Opens the chat with `text` typed in, e.g. to let a command be completed and sent by the user.
+/// If the chat is already open its text is replaced. Can be called from any thread,
+/// the chat is opened in the next frame. The string is UTF-8.
+///
+/// # Safety
+///
+/// `text` must be a valid null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn samp_cmd_helper_open_chat_with(text: *const c_char) -> bool {
+    if text.is_null() {
+        return false;
+    }
+    match CStr::from_ptr(text).to_str() {
+        Ok(text) => {
+            plugin::open_chat_with(text.to_string());
+            true
+        }
+        Err(_) => false,
+    }
+}
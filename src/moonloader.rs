@@ -9,20 +9,13 @@
  *
  *****************************************************************************/
 
-use std::ffi::{c_char, CStr};
+use std::ffi::c_char;
 use std::path::Path;
 
-use windows::{core::w, Win32::System::LibraryLoader::GetModuleHandleW};
-
 use crate::errors::Error;
 use crate::plugin::Plugin;
 use crate::utils;
 
-// libc
-extern "C" {
-    fn wcslen(buf: *const u16) -> usize;
-}
-
 #[derive(Debug, Clone, Copy, PartialOrd, PartialEq)]
 pub enum Version {
     V0265BetaArchive,
@@ -39,67 +32,96 @@ pub fn get_version(base_address: usize) -> Result<Version, Error> {
     }
 }
 
+type RegisterChatCommand = unsafe extern "C" fn(usize, *const c_char, u32, u32, u32, u32) -> u8;
+type UnregisterChatCommand = unsafe extern "C" fn(usize, *const c_char) -> u8;
+
 struct MoonLoaderHooks {
     name_offset: usize,
-    orig_samp_register_chat_command:
-        unsafe extern "C" fn(usize, *const c_char, u32, u32, u32, u32) -> u8,
-    orig_samp_unregister_chat_command: unsafe extern "C" fn(usize, *const c_char) -> u8,
+    orig_samp_register_chat_command: RegisterChatCommand,
+    orig_samp_unregister_chat_command: UnregisterChatCommand,
 }
 
 static mut MOONLOADER_HOOKS: Option<MoonLoaderHooks> = None;
+static mut FAILED: bool = false;
+
+// Longer paths are treated as garbage.
+const MAX_SCRIPT_PATH: usize = 32767;
 
 impl MoonLoaderHooks {
     pub fn new() -> Result<Self, Error> {
         unsafe {
-            let base_address = GetModuleHandleW(w!("MoonLoader.asi"))?.0 as usize;
-
-            match get_version(base_address)? {
-                Version::V0265BetaArchive => Ok(Self {
-                    name_offset: 0x18,
-                    orig_samp_register_chat_command: utils::replace_data_and_return_original(
-                        base_address + 0xF4438 + 0x4,
-                        Self::hk_orig_samp_register_chat_command,
-                    ),
-                    orig_samp_unregister_chat_command: utils::replace_data_and_return_original(
-                        base_address + 0xF44FE + 0x4,
-                        Self::hk_orig_samp_unregister_chat_command,
-                    ),
-                }),
-                Version::V0265BetaInstaller => Ok(Self {
-                    name_offset: 0x18,
-                    orig_samp_register_chat_command: utils::replace_data_and_return_original(
-                        base_address + 0xF3918 + 0x4,
-                        Self::hk_orig_samp_register_chat_command,
-                    ),
-                    orig_samp_unregister_chat_command: utils::replace_data_and_return_original(
-                        base_address + 0xF39DE + 0x4,
-                        Self::hk_orig_samp_unregister_chat_command,
-                    ),
-                }),
-                Version::V0270Preview3 => Ok(Self {
-                    name_offset: 0x34,
-                    orig_samp_register_chat_command: utils::replace_data_and_return_original(
-                        base_address + 0xDF0A4 + 0x1,
-                        Self::hk_orig_samp_register_chat_command,
-                    ),
-                    orig_samp_unregister_chat_command: utils::replace_data_and_return_original(
-                        base_address + 0xDF14C + 0x1,
-                        Self::hk_orig_samp_unregister_chat_command,
-                    ),
-                }),
+            let base_address = utils::get_module_handle("MoonLoader.asi")?.0 as usize;
+
+            let (name_offset, register_address, unregister_address) =
+                match get_version(base_address)? {
+                    Version::V0265BetaArchive => (0x18, 0xF4438 + 0x4, 0xF44FE + 0x4),
+                    Version::V0265BetaInstaller => (0x18, 0xF3918 + 0x4, 0xF39DE + 0x4),
+                    Version::V0270Preview3 => (0x34, 0xDF0A4 + 0x1, 0xDF14C + 0x1),
+                };
+            let register_address = base_address + register_address;
+            let unregister_address = base_address + unregister_address;
+            if !utils::is_readable_address(unregister_address) {
+                return Err(Error::InvalidAddress(unregister_address));
             }
+
+            let orig_samp_register_chat_command = utils::replace_data_and_return_original(
+                register_address,
+                Self::hk_orig_samp_register_chat_command as RegisterChatCommand,
+            )?;
+            let orig_samp_unregister_chat_command = match utils::replace_data_and_return_original(
+                unregister_address,
+                Self::hk_orig_samp_unregister_chat_command as UnregisterChatCommand,
+            ) {
+                Ok(original) => original,
+                Err(e) => {
+                    // The hook must not be called without MOONLOADER_HOOKS.
+                    if let Err(e) =
+                        utils::write_memory(register_address, orig_samp_register_chat_command)
+                    {
+                        eprintln!("moonloader::MoonLoaderHooks::new: {}", e);
+                    }
+                    return Err(e);
+                }
+            };
+
+            Ok(Self {
+                name_offset,
+                orig_samp_register_chat_command,
+                orig_samp_unregister_chat_command,
+            })
         }
     }
 
     pub unsafe fn get_script_name_from_userdata(&self, userdata: usize) -> String {
+        self.read_script_path(userdata)
+            .and_then(|path| {
+                Path::new(&path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+            })
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+
+    // A wrong name_offset for this version must not make us read arbitrary memory.
+    unsafe fn read_script_path(&self, userdata: usize) -> Option<String> {
         // Userdata is _G[".moonloader.this_script"]
-        let name = *((userdata + self.name_offset) as *const *const u16);
-        let name = String::from_utf16_lossy(std::slice::from_raw_parts(name, wcslen(name)));
-        if let Some(name) = Path::new(&name).file_name() {
-            name.to_string_lossy().to_string()
-        } else {
-            "unknown".to_string()
+        let slot = userdata.checked_add(self.name_offset)?;
+        if userdata == 0 || utils::readable_size(slot) < std::mem::size_of::<usize>() {
+            return None;
         }
+
+        let name = *(slot as *const *const u16);
+        if name.is_null() {
+            return None;
+        }
+
+        // The path must end within the region, the memory after it may not be mapped.
+        let max = MAX_SCRIPT_PATH.min(utils::readable_size(name as usize) / 2);
+        let len = utils::wcsnlen(name, max);
+        if len == max {
+            return None;
+        }
+        Some(utils::decode_utf16(std::slice::from_raw_parts(name, len)))
     }
 
     unsafe extern "C" fn hk_orig_samp_register_chat_command(
@@ -112,9 +134,10 @@ impl MoonLoaderHooks {
     ) -> u8 {
         let mh = MOONLOADER_HOOKS.as_ref().unwrap();
 
-        if let Ok(cmd) = CStr::from_ptr(cmd).to_str() {
+        let name = utils::decode_ansi_ptr(cmd);
+        if !name.is_empty() {
             let script_name = mh.get_script_name_from_userdata(userdata);
-            Plugin::get().add_lua_command(script_name, cmd);
+            Plugin::get().add_lua_command(script_name, &name);
         }
 
         (mh.orig_samp_register_chat_command)(userdata, cmd, a3, a4, a5, a6)
@@ -126,9 +149,10 @@ impl MoonLoaderHooks {
     ) -> u8 {
         let mh = MOONLOADER_HOOKS.as_ref().unwrap();
 
-        if let Ok(cmd) = CStr::from_ptr(cmd).to_str() {
+        let name = utils::decode_ansi_ptr(cmd);
+        if !name.is_empty() {
             let script_name = mh.get_script_name_from_userdata(userdata);
-            Plugin::get().remove_lua_command(&script_name, cmd);
+            Plugin::get().remove_lua_command(&script_name, &name);
         }
 
         (mh.orig_samp_unregister_chat_command)(userdata, cmd)
@@ -139,12 +163,25 @@ pub fn is_initialized() -> bool {
     unsafe { MOONLOADER_HOOKS.is_some() }
 }
 
+/// The module is present, but could not be used.
+pub fn has_failed() -> bool {
+    unsafe { FAILED }
+}
+
 pub fn initialize() -> Result<(), Error> {
+    // The hooks must not be installed over themselves.
+    if is_initialized() {
+        return Ok(());
+    }
+
     match MoonLoaderHooks::new() {
         Ok(v) => unsafe {
             MOONLOADER_HOOKS = Some(v);
             Ok(())
         },
-        Err(e) => Err(e),
+        Err(e) => unsafe {
+            FAILED = !matches!(e, Error::ModuleNotLoaded(_));
+            Err(e)
+        },
     }
 }
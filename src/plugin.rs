@@ -11,34 +11,44 @@
 
 use std::cell::OnceCell;
 use std::collections::HashMap;
-use std::ffi::CStr;
-use std::time::{Duration, SystemTime};
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
 
+use egui::{pos2, Pos2, Rect};
 use egui_d3d9::EguiDx9;
 use vmt_hook::VTableHook;
 use windows::{
-    core::{w, HRESULT},
+    core::{s, w, Interface, HRESULT},
     Win32::{
-        Foundation::{HWND, LPARAM, LRESULT, RECT, WPARAM},
+        Foundation::{HMODULE, HWND, LPARAM, LRESULT, RECT, WPARAM},
         Graphics::{
             Direct3D9::{IDirect3DDevice9, D3DPRESENT_PARAMETERS},
             Gdi::RGNDATA,
         },
         System::LibraryLoader::GetModuleHandleW,
+        UI::Input::KeyboardAndMouse::{GetKeyState, VK_CONTROL, VK_RETURN},
         UI::WindowsAndMessaging::{
-            CallWindowProcA, SetWindowLongPtrA, GWLP_WNDPROC, WM_LBUTTONDOWN, WM_MOUSEWHEEL,
-            WNDPROC,
+            CallWindowProcA, GetForegroundWindow, GetWindowLongPtrA, PostMessageA,
+            RegisterWindowMessageA, SendMessageA, SetWindowLongPtrA, GWLP_WNDPROC, WA_INACTIVE,
+            WM_ACTIVATE, WM_CHAR, WM_KEYDOWN, WM_KEYUP, WM_KILLFOCUS, WM_LBUTTONDBLCLK,
+            WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MBUTTONDOWN, WM_MBUTTONUP, WM_MOUSEMOVE,
+            WM_MOUSEWHEEL, WM_RBUTTONDOWN, WM_RBUTTONUP, WM_SETFOCUS, WNDPROC,
         },
     },
 };
 
 use crate::cmd_storage::{
-    cmd_with_prefix, Categories, Category, CategoryKey, CommandMap, ModuleMap,
+    self, cmd_with_prefix, Categories, Category, CategoryKey, CommandMap, Completion, ModuleMap,
+    CMD_PREFIX,
 };
+use crate::config::{self, Config, Descriptions, FileWatcher, InsertMode, Notes, Templates};
 use crate::errors::Error;
 use crate::gui::Ui;
-use crate::sampfuncs::{CmdOwner, CommandType};
-use crate::{gta, moonloader, samp, sampfuncs, utils};
+use crate::sampfuncs::CommandType;
+use crate::{gta, ipc, keys, moonloader, samp, sampfuncs, utils};
 
 type FnPresent = extern "stdcall" fn(
     IDirect3DDevice9,
@@ -50,50 +60,381 @@ type FnPresent = extern "stdcall" fn(
 
 type FnReset = extern "stdcall" fn(IDirect3DDevice9, *const D3DPRESENT_PARAMETERS) -> HRESULT;
 
+/// Extracts commands from a chat message.
+pub type ChatParser = fn(&str) -> Vec<String>;
+
+// Commands registered while playing are highlighted for this long.
+const RECENTLY_REGISTERED_TIME: Duration = Duration::from_secs(5);
+// SAMPFUNCS and MoonLoader may be loaded after us, they are looked for this long.
+const INTEGRATION_RETRY_TIME: Duration = Duration::from_secs(30);
+
+const CHAT_SUGGESTION_LINES: usize = 20;
+const MAX_CHAT_SUGGESTIONS: usize = 10;
+
+const D3D9_DEVICE_METHODS: usize = 119;
+const D3D9_DEVICE_RESET: usize = 16;
+const D3D9_DEVICE_PRESENT: usize = 17;
+
 enum InitState {
     BeforeSampInit,
     AfterSampInit,
     Initialized,
     Nothing,
+    Failed,
 }
 
 static mut FUNC_GTA_DEFINED_STATE: Option<unsafe extern "C" fn()> = None;
 
 static mut PLUGIN: Option<Plugin> = None;
 
+// Set while our D3D9 hooks use the plugin. Present or Reset called again from inside them
+// (e.g. by an overlay hooked on top of us) must go straight to the original,
+// otherwise there would be two mutable references to the plugin at once.
+static IN_D3D9_HOOK: AtomicBool = AtomicBool::new(false);
+
+// Kept outside of the plugin so that the re-entrant path doesn't have to take it.
+static mut ORIGINAL_RESET: Option<FnReset> = None;
+static mut ORIGINAL_PRESENT: Option<FnPresent> = None;
+
+// Sent to the window to find out whether our procedure is still called by the current one.
+static WND_PROC_PROBE: AtomicU32 = AtomicU32::new(0);
+static WND_PROC_PROBE_RECEIVED: AtomicBool = AtomicBool::new(false);
+
+// Other plugins may register their commands from any thread, they are applied in the game loop.
+static PENDING_SOURCES: Mutex<Vec<(String, Vec<String>)>> = Mutex::new(Vec::new());
+
+/// Commands of another plugin, shown as a category with the name of the source.
+/// Registering the same source again replaces its commands, no commands remove it.
+pub fn register_command_source(name: String, commands: Vec<String>) {
+    if let Ok(mut pending) = PENDING_SOURCES.lock() {
+        pending.push((name, commands));
+    }
+}
+
+// The chat can only be opened from the game thread, the last requested text wins.
+static PENDING_CHAT_TEXT: Mutex<Option<String>> = Mutex::new(None);
+
+/// Opens the chat with the text in the next frame.
+pub fn open_chat_with(text: String) {
+    if let Ok(mut pending) = PENDING_CHAT_TEXT.lock() {
+        *pending = Some(text);
+    }
+}
+
+// Registration of a Lua script while the updates are paused, applied on resume.
+enum LuaChange {
+    Add {
+        module: String,
+        command: String,
+    },
+    Remove {
+        script_name: String,
+        command: String,
+    },
+}
+
+impl LuaChange {
+    // Script and command.
+    fn target(&self) -> (&str, &str) {
+        match self {
+            Self::Add { module, command } => (module, command),
+            Self::Remove {
+                script_name,
+                command,
+            } => (script_name, command),
+        }
+    }
+}
+
+// Where a SA-MP command is handled, so that developers can find the function.
+struct SampHandler {
+    address: usize,
+    // Module and the offset of the address from its base.
+    location: Option<(String, usize)>,
+}
+
+impl SampHandler {
+    fn new(address: usize, modules: Option<&utils::ModuleList>) -> Self {
+        let location = modules
+            .and_then(|modules| modules.find_with_base(address as *const _))
+            .map(|(name, base)| (name.to_string(), address - base));
+        Self { address, location }
+    }
+}
+
+// Parameters of an inserted template. The edit box shows placeholders for the parameters not typed
+// yet, typing replaces the current one and Tab or a space moves to the next one.
+#[derive(Debug, Clone, PartialEq)]
+struct TemplateEdit {
+    command: String,
+    params: Vec<String>,
+    values: Vec<String>,
+    current: usize,
+}
+
+impl TemplateEdit {
+    // There must be at least one parameter.
+    fn new(command: &str, params: Vec<String>) -> Self {
+        Self {
+            command: command.to_string(),
+            values: vec![String::new(); params.len()],
+            params,
+            current: 0,
+        }
+    }
+
+    // What the chat shows, e.g. "/give 5 {amount}".
+    fn text(&self) -> String {
+        let mut text = self.command.clone();
+        for (param, value) in self.params.iter().zip(&self.values) {
+            if value.is_empty() {
+                text.push_str(&format!(" {{{}}}", param));
+            } else {
+                text.push(' ');
+                text.push_str(value);
+            }
+        }
+        text
+    }
+
+    // What is sent, the placeholders left are dropped.
+    fn filled_text(&self) -> String {
+        let mut text = self.command.clone();
+        for value in self.values.iter().filter(|v| !v.is_empty()) {
+            text.push(' ');
+            text.push_str(value);
+        }
+        text
+    }
+
+    fn type_char(&mut self, c: char) {
+        // The last parameter may be a message with spaces.
+        if c == ' ' && (self.next() || self.values[self.current].is_empty()) {
+            return;
+        }
+        self.values[self.current].push(c);
+    }
+
+    // An empty parameter goes back to the previous one. False if there is nothing left to erase.
+    fn backspace(&mut self) -> bool {
+        if self.values[self.current].pop().is_some() {
+            return true;
+        }
+        if self.current == 0 {
+            return false;
+        }
+        self.current -= 1;
+        true
+    }
+
+    fn next(&mut self) -> bool {
+        if self.current + 1 < self.params.len() {
+            self.current += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HookStatus {
+    pub defined_state: bool,
+    pub wnd_proc: bool,
+    pub d3d9_reset: bool,
+    pub d3d9_present: bool,
+    pub ui: bool,
+}
+
+impl HookStatus {
+    pub fn bits(&self) -> u32 {
+        (self.defined_state as u32)
+            | (self.wnd_proc as u32) << 1
+            | (self.d3d9_reset as u32) << 2
+            | (self.d3d9_present as u32) << 3
+            | (self.ui as u32) << 4
+    }
+}
+
 pub struct Plugin {
     d3d9_hook: Option<VTableHook<IDirect3DDevice9>>,
     gui: Option<EguiDx9<Ui>>,
     commands: Categories,
+    // Set once the commands have been parsed, until then the list would be empty or incomplete.
+    commands_ready: bool,
+    // The hooks leave the commands as they are, e.g. while a server registers them over and over.
+    updates_paused: bool,
+    // Lua commands are only known from the hooks, so their changes are kept until resumed.
+    paused_lua_changes: Vec<LuaChange>,
+    // What is displayed, the commands regrouped into custom categories.
+    grouped_commands: Categories,
     original_wnd_proc: Option<WNDPROC>,
-    original_reset: Option<FnReset>,
-    original_present: Option<FnPresent>,
     samp_base_address: usize,
     samp_version: samp::Version,
+    data_dir: PathBuf,
+    config: Config,
+    // config.json doesn't parse, saving would overwrite what the user has written.
+    config_broken: bool,
+    descriptions: Descriptions,
+    notes: Notes,
+    templates: Templates,
+    // Template of the inserted command while its parameters are typed.
+    active_template: Option<TemplateEdit>,
+    // Taken from chat messages of the current server.
+    announced_descriptions: Descriptions,
+    server_address: Option<String>,
+    last_server_check: Instant,
+    last_integration_retry: Instant,
+    integration_retry_until: Option<Instant>,
+    config_watcher: FileWatcher,
+    last_config_check: Instant,
+    // In pixels, updated every frame.
+    overlay_rects: Vec<Rect>,
+    cursor_pos: Option<Pos2>,
+    style_revision: u32,
+    // Changes whenever the shown commands may have to be filtered again.
+    filter_revision: u32,
+    hook_status: HookStatus,
+    present_calls: u32,
+    last_hooks_check: Instant,
+    last_wnd_proc_check: Instant,
+    settings_requested: bool,
+    // Key whose WM_CHAR must not reach the chat either.
+    swallowed_key: Option<u16>,
+    window_focused: bool,
+    // Hidden with the toggle key.
+    overlay_hidden: bool,
+    // Matching commands in the order they are drawn, rebuilt every frame.
+    selectable_commands: Vec<String>,
+    selected_command: Option<usize>,
+    // The list stays hidden until the chat text differs from the inserted command.
+    dismissed_text: Option<String>,
+    // Whether the commands or recalls list has been drawn in the last frame.
+    list_shown: bool,
+    // Hidden with Escape until the chat is opened again.
+    list_dismissed: bool,
+    chat_parser: ChatParser,
+    chat_suggestions: Vec<String>,
+    // Commands with prefix registered by the hooks.
+    registered_at: HashMap<String, Instant>,
+    // Command with prefix -> the SA-MP command handler.
+    samp_handlers: HashMap<String, SampHandler>,
+    // Shared by the commands registered in the same frame, ToolHelp snapshots are slow.
+    frame_modules: Option<utils::ModuleList>,
+    // The snapshot has been taken again for a handler it didn't have.
+    frame_modules_retaken: bool,
+    // Commands have been registered or removed by the hook, Lua scripts or other plugins,
+    // regrouped once in the game loop.
+    registered_commands_changed: bool,
+    // Command -> cleaned up name, only for the ones that differ.
+    display_names: HashMap<String, String>,
+    // Failed steps of post_initialize and of the hooks installed again later,
+    // written to a report for bug reports.
+    init_errors: Vec<String>,
+    // Set once initialization is over, later errors rewrite the report there.
+    init_report_path: Option<PathBuf>,
 }
 
 impl Plugin {
-    pub fn new(samp_base_address: usize, samp_version: samp::Version) -> Self {
+    pub fn new(samp_base_address: usize, samp_version: samp::Version, data_dir: PathBuf) -> Self {
+        let config_path = config::config_path(&data_dir);
+        let (config, config_broken) = match Config::load_if_exists(&config_path) {
+            Ok(Some(config)) => (config, false),
+            Ok(None) => {
+                // Write the defaults so that the user has a file to edit.
+                let config = Config::default();
+                if let Err(e) = config.save(&config_path) {
+                    eprintln!("config::save: {}", e);
+                }
+                (config, false)
+            }
+            // The defaults are used only in memory until the user fixes the file.
+            Err(e) => {
+                eprintln!("config::load: {}: {}", config_path.display(), e);
+                (Config::default(), true)
+            }
+        };
+
+        let (descriptions, templates) = config::load_descriptions(&data_dir, None);
+        let notes = config::load_notes(&data_dir);
+
+        let mut config_watcher = FileWatcher::default();
+        config_watcher.watch_config(&data_dir, None);
+
+        let commands = Categories {
+            order: vec![
+                CategoryKey::Samp,
+                CategoryKey::SfPlugin,
+                CategoryKey::Cleo,
+                CategoryKey::Lua,
+                CategoryKey::Documented,
+            ],
+            samp: Category::new("SA-MP".to_string()),
+            sf: Category::new("SF".to_string()),
+            cleo: Category::new("CLEO".to_string()),
+            lua: Category::new("Lua".to_string()),
+            documented: Category {
+                is_registered: false,
+                ..Category::new("Documented".to_string())
+            },
+            custom: Vec::new(),
+            external: Vec::new(),
+            duplicates: HashMap::new(),
+        };
+        let grouped_commands = commands.regroup(&config.custom_categories);
+
         Self {
             d3d9_hook: None,
             gui: None,
-            commands: Categories {
-                order: [
-                    CategoryKey::Samp,
-                    CategoryKey::SfPlugin,
-                    CategoryKey::Cleo,
-                    CategoryKey::Lua,
-                ],
-                samp: Category::new("SA-MP".to_string()),
-                sf: Category::new("SF".to_string()),
-                cleo: Category::new("CLEO".to_string()),
-                lua: Category::new("Lua".to_string()),
-            },
+            commands,
+            commands_ready: false,
+            updates_paused: false,
+            paused_lua_changes: Vec::new(),
+            grouped_commands,
             original_wnd_proc: None,
-            original_reset: None,
-            original_present: None,
             samp_base_address,
             samp_version,
+            data_dir,
+            config,
+            config_broken,
+            descriptions,
+            notes,
+            templates,
+            active_template: None,
+            announced_descriptions: Descriptions::new(),
+            server_address: None,
+            last_server_check: Instant::now(),
+            last_integration_retry: Instant::now(),
+            integration_retry_until: None,
+            config_watcher,
+            last_config_check: Instant::now(),
+            overlay_rects: Vec::new(),
+            cursor_pos: None,
+            style_revision: 0,
+            filter_revision: 0,
+            hook_status: HookStatus::default(),
+            present_calls: 0,
+            last_hooks_check: Instant::now(),
+            last_wnd_proc_check: Instant::now(),
+            settings_requested: false,
+            swallowed_key: None,
+            window_focused: true,
+            overlay_hidden: false,
+            selectable_commands: Vec::new(),
+            selected_command: None,
+            dismissed_text: None,
+            list_shown: false,
+            list_dismissed: false,
+            chat_parser: cmd_storage::extract_commands,
+            chat_suggestions: Vec::new(),
+            registered_at: HashMap::new(),
+            samp_handlers: HashMap::new(),
+            frame_modules: None,
+            frame_modules_retaken: false,
+            registered_commands_changed: false,
+            display_names: HashMap::new(),
+            init_errors: Vec::new(),
+            init_report_path: None,
         }
     }
 
@@ -107,36 +448,802 @@ impl Plugin {
         }
     }
 
+    // For exports that may be called before the plugin is initialized.
+    pub fn try_get<'a>() -> Option<&'a Plugin> {
+        unsafe { PLUGIN.as_ref() }
+    }
+
+    // Every step may fail independently, the status shows which ones did.
     pub fn post_initialize(&mut self) {
         unsafe {
-            self.install_wnd_proc();
             self.install_d3d9_hooks();
             self.init_ui();
+
+            // The window procedure is only needed for the UI.
+            if self.gui.is_some() {
+                self.install_wnd_proc();
+            }
+        }
+    }
+
+    fn init_error(&mut self, message: String) {
+        eprintln!("{}", message);
+        // A hook that keeps failing to install again would grow the report every check.
+        if self.init_errors.contains(&message) {
+            return;
+        }
+        self.init_errors.push(message);
+        if self.init_report_path.is_some() {
+            self.write_init_report();
+        }
+    }
+
+    /// Writes what is needed for a bug report to a local file if initialization has failed.
+    /// Called once initialization is over, then again for every new error. Nothing is sent anywhere.
+    pub fn write_init_report(&mut self) {
+        let path = self.init_report_path.get_or_insert_with(|| {
+            let timestamp = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            self.data_dir.join(format!("crash_{}.txt", timestamp))
+        });
+        if self.init_errors.is_empty() {
+            return;
+        }
+        let path = path.clone();
+
+        let status = self.hook_status;
+        let mut report = format!(
+            "samp-cmd-helper {}\n\
+             SA-MP: {:?} at {:#X}, entry point {:#X}\n\
+             SAMPFUNCS: {}, MoonLoader: {}\n\
+             Game loop hook: {}\n\
+             Window procedure hook: {}\n\
+             D3D9 Reset hook: {}\n\
+             D3D9 Present hook: {}\n\
+             User interface: {}\n\n",
+            env!("CARGO_PKG_VERSION"),
+            self.samp_version,
+            self.samp_base_address,
+            utils::get_entry_point(self.samp_base_address),
+            sampfuncs::is_initialized(),
+            moonloader::is_initialized(),
+            status.defined_state,
+            status.wnd_proc,
+            status.d3d9_reset,
+            status.d3d9_present,
+            status.ui,
+        );
+        for error in &self.init_errors {
+            report.push_str(error);
+            report.push('\n');
+        }
+
+        let result =
+            std::fs::create_dir_all(&self.data_dir).and_then(|()| std::fs::write(&path, report));
+        match result {
+            Ok(()) => eprintln!("plugin::write_init_report: {}", path.display()),
+            Err(e) => eprintln!("plugin::write_init_report: {}", e),
+        }
+    }
+
+    pub fn take_settings_request(&mut self) -> bool {
+        std::mem::take(&mut self.settings_requested)
+    }
+
+    // Called when Enter is pressed, returns true if the message must not reach the chat.
+    fn handle_settings_command(&mut self) -> bool {
+        let trigger = self.config.settings_command.trim();
+        if trigger.is_empty() {
+            return false;
+        }
+
+        let input = match samp::Input::get() {
+            Some(input) if input.enabled.as_bool() => input,
+            _ => return false,
+        };
+
+        let trigger = if trigger.starts_with(CMD_PREFIX) {
+            trigger.to_string()
+        } else {
+            cmd_with_prefix(trigger)
+        };
+
+        let text = input.edit_box().get_text();
+        let mut words = text.split_whitespace();
+        if !words
+            .next()
+            .is_some_and(|word| word.eq_ignore_ascii_case(&trigger))
+        {
+            return false;
+        }
+
+        // "/cmdhelper refresh" parses the commands again instead of opening the settings.
+        match (words.next(), words.next()) {
+            (None, _) => {
+                // Keep the chat open so that the cursor stays visible for the settings window.
+                input.edit_box().set_text("");
+                self.settings_requested = true;
+            }
+            (Some(arg), None) if arg.eq_ignore_ascii_case("refresh") => {
+                input.edit_box().set_text("");
+                self.refresh_commands();
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Parses the commands again, e.g. after scripts have been reloaded or the server has changed.
+    /// Hidden commands and other settings are kept, they are stored by name.
+    pub fn refresh_commands(&mut self) {
+        if samp::Input::get().is_none() {
+            return;
+        }
+
+        // Lua commands are tracked by the hooks and stay as they are.
+        for category in [&mut self.commands.sf, &mut self.commands.cleo] {
+            category.modules.clear();
+            category.is_visible = false;
+        }
+        // CInput is read again even with the hook, it has missed the commands registered while paused.
+        if samp::is_add_command_hooked() {
+            self.parse_samp_commands();
+        }
+        self.parse_commands();
+    }
+
+    pub fn are_updates_paused(&self) -> bool {
+        self.updates_paused
+    }
+
+    /// Freezes the commands. On resume they are parsed again, the Lua changes made in the meantime
+    /// are applied and the commands of other plugins are taken from their queue.
+    pub fn set_updates_paused(&mut self, paused: bool) {
+        if self.updates_paused == paused {
+            return;
+        }
+        self.updates_paused = paused;
+        if paused {
+            return;
+        }
+
+        for change in std::mem::take(&mut self.paused_lua_changes) {
+            match change {
+                LuaChange::Add { module, command } => self.add_lua_command(module, &command),
+                LuaChange::Remove {
+                    script_name,
+                    command,
+                } => self.remove_lua_command(&script_name, &command),
+            }
+        }
+        self.refresh_commands();
+    }
+
+    // Only the last change of a command matters, so a script registering it over and over
+    // doesn't grow the queue.
+    fn queue_lua_change(&mut self, change: LuaChange) {
+        self.paused_lua_changes
+            .retain(|queued| queued.target() != change.target());
+        self.paused_lua_changes.push(change);
+    }
+
+    pub fn clear_selectable_commands(&mut self) {
+        self.selectable_commands.clear();
+    }
+
+    // Returns true if the command is the one selected with the keyboard.
+    pub fn add_selectable_command(&mut self, command: &str) -> bool {
+        let index = self.selectable_commands.len();
+        self.selectable_commands.push(command.to_string());
+        self.selected_command == Some(index)
+    }
+
+    pub fn chat_closed(&mut self) {
+        self.selected_command = None;
+        self.list_dismissed = false;
+    }
+
+    pub fn set_list_shown(&mut self, shown: bool) {
+        self.list_shown = shown;
+    }
+
+    pub fn is_list_dismissed(&self) -> bool {
+        self.list_dismissed
+    }
+
+    // The first Escape hides our list, the next one closes the chat as usual.
+    fn dismiss_list(&mut self) -> bool {
+        if !self.list_shown || self.list_dismissed {
+            return false;
+        }
+        self.list_dismissed = true;
+        self.list_shown = false;
+        true
+    }
+
+    pub fn is_overlay_dismissed(&mut self, chat_text: &str) -> bool {
+        match &self.dismissed_text {
+            Some(text) if text == chat_text => true,
+            Some(_) => {
+                self.dismissed_text = None;
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn is_bound(binding: &str, key: u16) -> bool {
+        keys::parse(binding).and_then(keys::virtual_key) == Some(key)
+    }
+
+    // Keys released and the cursor moved while another window was active are never seen,
+    // so nothing that depends on them may survive the focus loss.
+    fn set_window_focused(&mut self, focused: bool) {
+        if !focused {
+            self.cursor_pos = None;
+            self.swallowed_key = None;
+            self.selected_command = None;
+        }
+        self.window_focused = focused;
+    }
+
+    pub fn is_window_focused(&self) -> bool {
+        self.window_focused
+    }
+
+    pub fn is_overlay_hidden(&self) -> bool {
+        self.overlay_hidden
+    }
+
+    // Returns true if the key must not reach the chat, i.e. it would be typed there.
+    fn handle_toggle_key(&mut self, key: u16) -> bool {
+        if !Self::is_bound(&self.config.keys.toggle_overlay, key) {
+            return false;
+        }
+        self.overlay_hidden = !self.overlay_hidden;
+        samp::Input::get().is_some_and(|input| input.enabled.as_bool())
+    }
+
+    // Ctrl+Up/Down select a command, Enter inserts it and hides the list, Ctrl+Enter keeps the list open.
+    // The keys can be rebound, the modifiers can't.
+    // Returns true if the key must not reach the chat.
+    fn handle_navigation_key(&mut self, key: u16) -> bool {
+        let count = self.selectable_commands.len();
+        if count == 0 {
+            self.selected_command = None;
+            return false;
+        }
+
+        let ctrl = unsafe { GetKeyState(VK_CONTROL.0 as i32) } < 0;
+        let selected = self.selected_command.filter(|&i| i < count);
+
+        let bindings = &self.config.keys;
+        if ctrl && Self::is_bound(&bindings.select_next, key) {
+            self.selected_command = Some(selected.map_or(0, |i| (i + 1) % count));
+            true
+        } else if ctrl && Self::is_bound(&bindings.select_previous, key) {
+            self.selected_command = Some(selected.map_or(count - 1, |i| (i + count - 1) % count));
+            true
+        } else if Self::is_bound(&bindings.insert, key) {
+            match selected {
+                Some(index) => self.insert_selectable_command(index, ctrl),
+                None => false,
+            }
+        } else {
+            false
+        }
+    }
+
+    // Of the first category that has registered the command, custom categories don't change it.
+    fn insert_mode_of(&self, command: &str) -> InsertMode {
+        self.commands
+            .keyed()
+            .find(|(_, c)| c.modules.values().any(|cmds| cmds.contains_key(command)))
+            .and_then(|(key, _)| self.config.insert_modes.get(&key))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Text to put into the chat for the command. A command with a template is followed by
+    /// the placeholders of its parameters, they are filled in until the text is changed otherwise.
+    fn command_insert_text(&mut self, command: &str, mode: InsertMode) -> String {
+        let text = if mode.prefix {
+            command
+        } else {
+            command.strip_prefix(CMD_PREFIX).unwrap_or(command)
+        };
+        match self.templates.get(command) {
+            Some(params) if !mode.send && !params.is_empty() => {
+                let edit = TemplateEdit::new(text, params.clone());
+                let text = edit.text();
+                self.active_template = Some(edit);
+                text
+            }
+            _ => {
+                self.active_template = None;
+                text.to_string()
+            }
+        }
+    }
+
+    /// Puts the command into the chat and sends it if its category asks for it.
+    /// Returns the inserted text.
+    pub fn insert_command(&mut self, input: &mut samp::Input, command: &str) -> Option<String> {
+        let mode = self.insert_mode_of(command);
+        let text = self.command_insert_text(command, mode);
+        if !input.edit_box().set_text(&text) {
+            return None;
+        }
+        if mode.send {
+            // As if Enter has been pressed, SA-MP sends the chat input itself.
+            unsafe {
+                let window = gta::get_window_handle();
+                let _ = PostMessageA(window, WM_KEYDOWN, WPARAM(VK_RETURN.0 as usize), LPARAM(0));
+                let _ = PostMessageA(window, WM_KEYUP, WPARAM(VK_RETURN.0 as usize), LPARAM(0));
+            }
+        }
+        Some(text)
+    }
+
+    /// The inserted command, its parameters and the index of the one being typed.
+    /// The template is dropped once the chat text has been changed not by filling it in.
+    pub fn active_template(&mut self, chat_text: &str) -> Option<(&str, &[String], usize)> {
+        if self
+            .active_template
+            .as_ref()
+            .is_some_and(|edit| edit.text() != chat_text)
+        {
+            self.active_template = None;
+        }
+        self.active_template
+            .as_ref()
+            .map(|edit| (edit.command.as_str(), edit.params.as_slice(), edit.current))
+    }
+
+    // The chat input while it still shows the inserted template.
+    fn template_input<'a>(&mut self) -> Option<&'a mut samp::Input> {
+        let input = match samp::Input::get() {
+            Some(input) if input.enabled.as_bool() => input,
+            _ => return None,
+        };
+        let text = input.edit_box().get_text();
+        self.active_template(&text)?;
+        Some(input)
+    }
+
+    // Tab moves to the next parameter of the template, like in IDEs.
+    fn next_template_param(&mut self) -> bool {
+        let Some(input) = self.template_input() else {
+            return false;
+        };
+        let Some(edit) = self.active_template.as_mut() else {
+            return false;
+        };
+        edit.next() && input.edit_box().set_text(&edit.text())
+    }
+
+    // Typing replaces the placeholder of the current parameter.
+    // Returns true if the character must not reach the chat.
+    fn handle_template_char(&mut self, ch: u8) -> bool {
+        const BACKSPACE: u8 = 0x08;
+
+        let Some(input) = self.template_input() else {
+            return false;
+        };
+        let Some(edit) = self.active_template.as_mut() else {
+            return false;
+        };
+        if ch == BACKSPACE {
+            if !edit.backspace() {
+                let text = edit.filled_text();
+                self.active_template = None;
+                return input.edit_box().set_text(&text);
+            }
+        } else {
+            // Anything but a single printable character is left to the edit box.
+            let text = utils::decode_ansi(&[ch]);
+            let mut chars = text.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if !c.is_control() => edit.type_char(c),
+                _ => return false,
+            }
+        }
+        input.edit_box().set_text(&edit.text())
+    }
+
+    // The placeholders that have not been filled in must not be sent.
+    fn finish_template(&mut self) {
+        let Some(input) = self.template_input() else {
+            return;
+        };
+        if let Some(edit) = self.active_template.take() {
+            input.edit_box().set_text(&edit.filled_text());
+        }
+    }
+
+    // Like in shells, Tab extends the typed text to the common beginning of the matching commands
+    // or inserts the command if only one matches.
+    fn handle_complete_key(&mut self, key: u16) -> bool {
+        if !Self::is_bound(&self.config.keys.complete, key) {
+            return false;
+        }
+        if self.next_template_param() {
+            return true;
+        }
+        if !self.list_shown {
+            return false;
+        }
+        let input = match samp::Input::get() {
+            Some(input) if input.enabled.as_bool() => input,
+            _ => return false,
+        };
+        let text = input.edit_box().get_text();
+        match cmd_storage::complete(&text, &self.selectable_commands) {
+            Some(Completion::Command(command)) => {
+                let index = self.selectable_commands.iter().position(|c| c == command);
+                index.is_some_and(|index| self.insert_selectable_command(index, false))
+            }
+            Some(Completion::Prefix(prefix)) => input.edit_box().set_text(prefix),
+            None => false,
+        }
+    }
+
+    pub fn selectable_command_count(&self) -> usize {
+        self.selectable_commands.len()
+    }
+
+    // Puts the command into the chat, the list is hidden unless asked to keep it.
+    pub fn insert_selectable_command(&mut self, index: usize, keep_open: bool) -> bool {
+        let command = match self.selectable_commands.get(index) {
+            Some(command) => command.clone(),
+            None => return false,
+        };
+        let input = match samp::Input::get() {
+            Some(input) if input.enabled.as_bool() => input,
+            _ => return false,
+        };
+
+        if self.needs_confirmation(&command) {
+            if let Some(gui) = &self.gui {
+                Ui::request_confirmation(gui.ctx(), &command);
+            }
+            self.selected_command = None;
+            return true;
         }
+
+        let Some(text) = self.insert_command(input, &command) else {
+            return false;
+        };
+        if let Some(gui) = &self.gui {
+            Ui::flash_inserted(gui.ctx(), &command);
+        }
+        self.selected_command = None;
+        if !keep_open {
+            self.dismissed_text = Some(text);
+        }
+        true
+    }
+
+    pub fn hook_status(&self) -> HookStatus {
+        self.hook_status
+    }
+
+    pub fn samp_version(&self) -> samp::Version {
+        self.samp_version
+    }
+
+    pub fn samp_base_address(&self) -> usize {
+        self.samp_base_address
     }
 
     pub fn commands(&self) -> &Categories {
+        &self.grouped_commands
+    }
+
+    /// As they have been registered, before custom categories and merging.
+    pub fn source_commands(&self) -> &Categories {
         &self.commands
     }
 
-    pub fn parse_commands(&mut self) {
-        // Todo: Prefer placing hooks on command registration and removal rather than parsing them once.
+    pub fn note_of(&self, command: &str) -> Option<&str> {
+        self.notes.get(command).map(String::as_str)
+    }
 
-        let samp_cmds: HashMap<String, Vec<String>> = self.get_samp_commands_grouped_by_module();
-        let samp_modules = samp_cmds
-            .into_iter()
-            .map(|(module, cmds)| {
-                (
-                    module,
-                    cmds.iter()
-                        .map(|cmd| (cmd_with_prefix(cmd), String::default()))
-                        .collect(),
-                )
+    /// An empty note removes it.
+    pub fn set_note(&mut self, command: &str, note: &str) {
+        let note = note.trim();
+        if note.is_empty() {
+            self.notes.remove(command);
+        } else {
+            self.notes.insert(command.to_string(), note.to_string());
+        }
+        if let Err(e) = config::save_notes(&self.data_dir, &self.notes) {
+            eprintln!("config::save_notes: {}", e);
+        }
+    }
+
+    pub fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    pub fn server_address(&self) -> Option<&str> {
+        self.server_address.as_deref()
+    }
+
+    pub fn clear_overlay_rects(&mut self) {
+        self.overlay_rects.clear();
+    }
+
+    pub fn add_overlay_rect(&mut self, rect: Rect) {
+        self.overlay_rects.push(rect);
+    }
+
+    fn is_cursor_over_overlay(&self) -> bool {
+        match self.cursor_pos {
+            Some(pos) => self.overlay_rects.iter().any(|r| r.contains(pos)),
+            None => false,
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn config_mut(&mut self) -> &mut Config {
+        &mut self.config
+    }
+
+    pub fn toggle_hidden_command(&mut self, command: &str) {
+        if !self.config.hidden_commands.remove(command) {
+            self.config.hidden_commands.insert(command.to_string());
+        }
+        self.save_config();
+    }
+
+    pub fn needs_confirmation(&self, command: &str) -> bool {
+        self.config.confirm_commands.contains(command)
+    }
+
+    pub fn toggle_confirm_command(&mut self, command: &str) {
+        if !self.config.confirm_commands.remove(command) {
+            self.config.confirm_commands.insert(command.to_string());
+        }
+        self.save_config();
+    }
+
+    pub fn toggle_ignored_module(&mut self, module: &str) {
+        let ignored = self.config.ignored_modules.len();
+        self.config
+            .ignored_modules
+            .retain(|ignored| !ignored.eq_ignore_ascii_case(module));
+        if self.config.ignored_modules.len() == ignored {
+            self.config.ignored_modules.insert(module.to_string());
+        }
+        self.save_config();
+        self.commands_changed();
+    }
+
+    pub fn style_revision(&self) -> u32 {
+        self.style_revision
+    }
+
+    // Makes the UI apply style settings again.
+    pub fn invalidate_style(&mut self) {
+        self.style_revision = self.style_revision.wrapping_add(1);
+    }
+
+    pub fn filter_revision(&self) -> u32 {
+        self.filter_revision
+    }
+
+    // Makes the UI filter the commands again, the config affects what is shown too.
+    fn invalidate_filter(&mut self) {
+        self.filter_revision = self.filter_revision.wrapping_add(1);
+    }
+
+    pub fn save_config(&mut self) {
+        let path = config::config_path(&self.data_dir);
+        self.invalidate_filter();
+        // One typo must not cost the user all the settings in the file.
+        if self.config_broken {
+            eprintln!(
+                "plugin::save_config: {} has errors, it is not overwritten until they are fixed",
+                path.display()
+            );
+            return;
+        }
+        if let Err(e) = self.config.save(&path) {
+            eprintln!("config::save: {}", e);
+        }
+
+        // Don't reload what we have just written.
+        self.config_watcher.watch(path);
+    }
+
+    // The user may edit the files while in game. Checked rarely to avoid disk I/O every frame.
+    pub fn reload_changed_config(&mut self) {
+        if self.last_config_check.elapsed() < Duration::from_secs(2) {
+            return;
+        }
+        self.last_config_check = Instant::now();
+
+        if self.config_watcher.changed() {
+            let path = config::config_path(&self.data_dir);
+            match Config::load_if_exists(&path) {
+                Ok(Some(config)) => self.config = config,
+                // Deleted, the current settings are written again on the next save.
+                Ok(None) => {}
+                Err(e) => {
+                    // Keep what has been loaded before.
+                    eprintln!("config::load: {}: {}", path.display(), e);
+                    self.config_broken = true;
+                    return;
+                }
+            }
+            self.config_broken = false;
+            self.invalidate_style();
+            self.invalidate_filter();
+            self.apply_code_page_config();
+            self.apply_ipc_config();
+            self.apply_chat_hook_config();
+            (self.descriptions, self.templates) =
+                config::load_descriptions(&self.data_dir, self.server_address.as_deref());
+            self.apply_descriptions();
+        }
+    }
+
+    pub fn apply_code_page_config(&self) {
+        if !utils::set_ansi_code_page(self.config.code_page) {
+            eprintln!(
+                "plugin::apply_code_page_config: code page {} is not valid, the previous one is used",
+                self.config.code_page
+            );
+        }
+    }
+
+    // The hook stays installed when disabled, the messages are just ignored then.
+    pub fn apply_chat_hook_config(&self) {
+        if self.config.server_descriptions || self.config.chat_suggestions {
+            if let Err(e) = samp::install_chat_hook() {
+                eprintln!("samp::install_chat_hook: {}", e);
+            }
+        }
+    }
+
+    /// For servers that mention commands in their own way, e.g. without the prefix.
+    // Nothing in the plugin replaces the default parser.
+    #[allow(dead_code)]
+    pub fn set_chat_parser(&mut self, parser: ChatParser) {
+        self.chat_parser = parser;
+    }
+
+    /// Registered commands mentioned in recent chat messages, the most recent first.
+    pub fn chat_suggestions(&self) -> &[String] {
+        &self.chat_suggestions
+    }
+
+    fn update_chat_suggestions(&mut self) {
+        let lines = samp::get_recent_chat_lines(CHAT_SUGGESTION_LINES);
+        let commands = &self.commands;
+        self.chat_suggestions = suggest_from_chat(&lines, self.chat_parser, |command| {
+            commands.contains(command)
+        });
+    }
+
+    pub fn handle_chat_message(&mut self, message: &str) {
+        if self.config.chat_suggestions {
+            self.update_chat_suggestions();
+        }
+
+        if !self.config.server_descriptions {
+            return;
+        }
+
+        if let Some((command, description)) =
+            config::parse_announced_description(&self.config.server_description_format, message)
+        {
+            if self.announced_descriptions.get(&command) != Some(&description) {
+                self.announced_descriptions.insert(command, description);
+                self.apply_descriptions();
+            }
+        }
+    }
+
+    // Descriptions from files take precedence over announced ones, the user may have corrected them.
+    fn merged_descriptions(&self) -> Descriptions {
+        let mut descriptions = self.announced_descriptions.clone();
+        descriptions.extend(
+            self.descriptions
+                .iter()
+                .map(|(cmd, description)| (cmd.clone(), description.clone())),
+        );
+        descriptions
+    }
+
+    pub fn is_recently_registered(&self, command: &str) -> bool {
+        self.registered_at
+            .get(command)
+            .is_some_and(|time| time.elapsed() < RECENTLY_REGISTERED_TIME)
+    }
+
+    pub fn refresh_documented(&mut self) {
+        let descriptions = self.merged_descriptions();
+        self.update_documented(&descriptions);
+        self.commands_changed();
+    }
+
+    fn apply_descriptions(&mut self) {
+        let descriptions = self.merged_descriptions();
+        self.update_documented(&descriptions);
+        self.commands.apply_descriptions(&descriptions);
+        self.commands_changed();
+    }
+
+    // Commands with a description that nothing has registered, e.g. server commands
+    // or commands of a script that is not loaded.
+    fn update_documented(&mut self, descriptions: &Descriptions) {
+        let mut documented = CommandMap::new();
+        for (cmd, description) in descriptions {
+            if !self.commands.contains(cmd) {
+                documented.insert(cmd.clone(), description.clone());
+            }
+        }
+
+        let category = &mut self.commands.documented;
+        category.is_visible = self.config.show_documented && !documented.is_empty();
+        category.modules.clear();
+        if !documented.is_empty() {
+            category
+                .modules
+                .insert("Descriptions".to_string(), documented);
+        }
+    }
+
+    pub fn apply_ipc_config(&self) {
+        ipc::set_enabled(self.config.ipc_pipe, &self.grouped_commands);
+    }
+
+    /// Rebuilds what is displayed, also after changing how the commands are grouped.
+    pub fn commands_changed(&mut self) {
+        let mut grouped = self.commands.regroup(&self.config.custom_categories);
+        grouped.remove_modules(|module| self.config.is_module_ignored(module));
+        grouped.merge_duplicates(self.config.duplicate_mode, &self.config.duplicate_priority);
+        self.grouped_commands = grouped;
+        self.update_display_names();
+        self.invalidate_filter();
+        ipc::update_snapshot(&self.grouped_commands);
+    }
+
+    fn update_display_names(&mut self) {
+        let rules = &self.config.name_normalization;
+        self.display_names = self
+            .grouped_commands
+            .iter()
+            .flat_map(|category| category.modules.values())
+            .flat_map(|commands| commands.keys())
+            .filter_map(|cmd| {
+                let name = cmd_storage::normalize_command_name(cmd, rules);
+                (name != *cmd).then(|| (cmd.clone(), name))
             })
             .collect();
-        let samp = &mut self.commands.samp;
-        samp.modules = samp_modules;
-        samp.is_visible = true;
+    }
+
+    /// How the command is shown, it is still inserted as registered.
+    pub fn display_name<'a>(&'a self, command: &'a str) -> &'a str {
+        self.display_names
+            .get(command)
+            .map_or(command, String::as_str)
+    }
+
+    pub fn parse_commands(&mut self) {
+        // Todo: Prefer placing hooks on SAMPFUNCS command registration and removal rather than parsing them once.
+
+        // With the hook the SA-MP commands are already there.
+        if !samp::is_add_command_hooked() {
+            self.parse_samp_commands();
+        }
 
         if let Some(mut sf_cmds) = self.get_sampfuncs_commands_grouped() {
             const MOONLOADER_SCM_THREAD: &str = "moonldr.cs";
@@ -175,22 +1282,226 @@ impl Plugin {
                 cleo.is_visible = true;
             }
         }
+
+        self.apply_descriptions();
+        self.commands_ready = true;
+    }
+
+    fn parse_samp_commands(&mut self) {
+        let samp_cmds: HashMap<String, Vec<String>> = self.get_samp_commands_grouped_by_module();
+        let samp_modules = samp_cmds
+            .into_iter()
+            .map(|(module, cmds)| {
+                (
+                    module,
+                    cmds.iter()
+                        .map(|cmd| (cmd_with_prefix(cmd), String::default()))
+                        .collect(),
+                )
+            })
+            .collect();
+        self.samp_handlers = self.get_samp_command_handlers();
+        let samp = &mut self.commands.samp;
+        samp.modules = samp_modules;
+        samp.is_visible = true;
+    }
+
+    pub fn commands_ready(&self) -> bool {
+        self.commands_ready
+    }
+
+    // Descriptions depend on the server, so reload them when it changes.
+    pub fn update_server(&mut self) {
+        if self.last_server_check.elapsed() < Duration::from_secs(1) {
+            return;
+        }
+        self.last_server_check = Instant::now();
+
+        let server_address = samp::get_server_address();
+        if server_address != self.server_address {
+            (self.descriptions, self.templates) =
+                config::load_descriptions(&self.data_dir, server_address.as_deref());
+            self.announced_descriptions.clear();
+            self.apply_descriptions();
+            self.config_watcher
+                .watch_config(&self.data_dir, server_address.as_deref());
+            self.server_address = server_address;
+        }
+    }
+
+    fn description_of(&self, command: &str) -> String {
+        self.descriptions
+            .get(command)
+            .or_else(|| self.announced_descriptions.get(command))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn mark_registered(&mut self, command: &str) {
+        self.registered_at
+            .retain(|_, time| time.elapsed() < RECENTLY_REGISTERED_TIME);
+        self.registered_at
+            .insert(command.to_string(), Instant::now());
+    }
+
+    /// Called by the CInput::AddCommand hook.
+    pub fn add_samp_command(&mut self, command: &str, handler: usize) {
+        if self.updates_paused {
+            return;
+        }
+        let command = cmd_with_prefix(command);
+        let description = self.description_of(&command);
+        let handler = self.locate_samp_handler(handler);
+        let module = handler
+            .location
+            .as_ref()
+            .map_or_else(|| "unknown".to_string(), |(name, _)| name.clone());
+
+        self.mark_registered(&command);
+        self.samp_handlers.insert(command.clone(), handler);
+
+        let category = &mut self.commands.samp;
+        category.is_visible = true;
+        category
+            .modules
+            .entry(module)
+            .or_default()
+            .insert(command, description);
+
+        self.registered_commands_changed = true;
+    }
+
+    // The module of the handler may have been loaded after the snapshot, then it is taken again,
+    // but only once per frame.
+    fn locate_samp_handler(&mut self, address: usize) -> SampHandler {
+        if let Some(modules) = &self.frame_modules {
+            let handler = SampHandler::new(address, Some(modules));
+            if handler.location.is_some() || self.frame_modules_retaken {
+                return handler;
+            }
+            self.frame_modules_retaken = true;
+        }
+        self.frame_modules = utils::ModuleList::snapshot();
+        SampHandler::new(address, self.frame_modules.as_ref())
+    }
+
+    // Once per frame, however many commands have been registered or removed.
+    fn apply_registered_commands(&mut self) {
+        self.frame_modules = None;
+        self.frame_modules_retaken = false;
+        if std::mem::take(&mut self.registered_commands_changed) {
+            self.refresh_documented();
+        }
     }
 
     pub fn add_lua_command(&mut self, module: String, command: &str) {
+        if self.updates_paused {
+            let command = command.to_string();
+            self.queue_lua_change(LuaChange::Add { module, command });
+            return;
+        }
+        let command = cmd_with_prefix(command);
+        let description = self.description_of(&command);
+        self.mark_registered(&command);
+
         let category = &mut self.commands.lua;
         category.is_visible = true;
         category
             .modules
             .entry(module)
-            .or_insert(CommandMap::from([(
-                cmd_with_prefix(command),
-                String::default(),
-            )]))
-            .insert(cmd_with_prefix(command), String::default());
+            .or_default()
+            .insert(command, description);
+
+        self.registered_commands_changed = true;
+    }
+
+    // Modules that failed are not retried, only absent ones.
+    fn retry_integrations(&mut self) {
+        let Some(until) = self.integration_retry_until else {
+            return;
+        };
+        let sf_pending = !sampfuncs::is_initialized() && !sampfuncs::has_failed();
+        let ml_pending = !moonloader::is_initialized() && !moonloader::has_failed();
+        if Instant::now() > until || (!sf_pending && !ml_pending) {
+            self.integration_retry_until = None;
+            return;
+        }
+        if self.last_integration_retry.elapsed() < Duration::from_secs(1) {
+            return;
+        }
+        self.last_integration_retry = Instant::now();
+
+        if ml_pending {
+            match moonloader::initialize() {
+                Ok(()) | Err(Error::ModuleNotLoaded(_)) => {}
+                Err(e) => self.init_error(format!("moonloader::initialize: {}", e)),
+            }
+        }
+
+        if sf_pending {
+            match unsafe { sampfuncs::initialize() } {
+                // Its commands were not there when they were parsed.
+                Ok(()) => self.parse_commands(),
+                Err(Error::ModuleNotLoaded(_)) => {}
+                Err(e) => self.init_error(format!("sampfuncs::initialize: {}", e)),
+            }
+        }
+    }
+
+    fn apply_pending_sources(&mut self) {
+        // Kept in the queue until resumed.
+        if self.updates_paused {
+            return;
+        }
+        let pending = match PENDING_SOURCES.lock() {
+            Ok(mut pending) => std::mem::take(&mut *pending),
+            Err(_) => return,
+        };
+        if pending.is_empty() {
+            return;
+        }
+
+        for (name, commands) in pending {
+            let commands = commands
+                .iter()
+                .filter(|cmd| !cmd.is_empty())
+                .map(|cmd| {
+                    let cmd = if cmd.starts_with(CMD_PREFIX) {
+                        cmd.clone()
+                    } else {
+                        cmd_with_prefix(cmd)
+                    };
+                    self.mark_registered(&cmd);
+                    let description = self.description_of(&cmd);
+                    (cmd, description)
+                })
+                .collect();
+            self.commands.set_external(&name, commands);
+        }
+
+        self.registered_commands_changed = true;
+    }
+
+    fn apply_pending_chat_text(&mut self) {
+        let text = match PENDING_CHAT_TEXT.lock() {
+            Ok(mut pending) => pending.take(),
+            Err(_) => return,
+        };
+        if let Some(text) = text {
+            if !samp::open_chat_with(&text) {
+                eprintln!("plugin::apply_pending_chat_text: the chat can't be opened");
+            }
+        }
     }
 
     pub fn remove_lua_command(&mut self, script_name: &str, command: &str) {
+        if self.updates_paused {
+            self.queue_lua_change(LuaChange::Remove {
+                script_name: script_name.to_string(),
+                command: command.to_string(),
+            });
+            return;
+        }
         let category = &mut self.commands.lua.modules;
         if let Some(module) = category.get_mut(script_name) {
             module.remove(&cmd_with_prefix(command));
@@ -204,41 +1515,178 @@ impl Plugin {
                     self.commands.lua.is_visible = false;
                 }
             }
+
+            self.registered_commands_changed = true;
         }
     }
 
     unsafe fn install_wnd_proc(&mut self) {
         let window = gta::get_window_handle();
 
-        let old_proc = SetWindowLongPtrA(window, GWLP_WNDPROC, Self::hk_wnd_proc as i32);
-        self.original_wnd_proc = Some(std::mem::transmute(old_proc));
+        if WND_PROC_PROBE.load(Ordering::Relaxed) == 0 {
+            let probe = RegisterWindowMessageA(s!("samp-cmd-helper-wnd-proc-probe"));
+            WND_PROC_PROBE.store(probe, Ordering::Relaxed);
+        }
+
+        // LONG_PTR is i32 on x86 and isize on x64, the address is passed as is in both cases.
+        // Addresses above 2 GB (large address aware game) become negative, that's expected.
+        let old_proc = SetWindowLongPtrA(window, GWLP_WNDPROC, Self::hk_wnd_proc as usize as _);
+        if old_proc == 0 {
+            self.init_error(format!(
+                "plugin::install_wnd_proc: {}",
+                windows::core::Error::from_win32()
+            ));
+            return;
+        }
+
+        self.original_wnd_proc = Some(std::mem::transmute::<usize, WNDPROC>(old_proc as usize));
+        self.hook_status.wnd_proc = true;
     }
 
     unsafe fn install_d3d9_hooks(&mut self) {
-        let hook = VTableHook::with_count(gta::get_d3d9_device(), 119);
+        let device = match gta::get_d3d9_device() {
+            Some(device) => device,
+            None => {
+                self.init_error(
+                    "plugin::install_d3d9_hooks: D3D9 device is not created".to_string(),
+                );
+                return;
+            }
+        };
+
+        // Wrappers such as ENB or ReShade may replace the device, so don't trust the vtable blindly.
+        let methods_count = utils::count_vtable_methods(device.as_raw(), D3D9_DEVICE_METHODS);
+        if methods_count != D3D9_DEVICE_METHODS {
+            eprintln!(
+                "plugin::install_d3d9_hooks: D3D9 device vtable has {} of {} methods",
+                methods_count, D3D9_DEVICE_METHODS
+            );
+        }
+        if methods_count <= D3D9_DEVICE_PRESENT.max(D3D9_DEVICE_RESET) {
+            self.init_error(format!(
+                "plugin::install_d3d9_hooks: invalid D3D9 device vtable of {} methods",
+                methods_count
+            ));
+            // The reference is borrowed from the game, it must not be released.
+            std::mem::forget(device);
+            return;
+        }
+
+        // Only the entries that have been checked are copied, a shorter vtable must not be overread.
+        let hook = VTableHook::with_count(device, methods_count);
 
-        self.original_reset = Some(std::mem::transmute(hook.get_original_method(16)));
-        self.original_present = Some(std::mem::transmute(hook.get_original_method(17)));
+        ORIGINAL_RESET = Some(std::mem::transmute(
+            hook.get_original_method(D3D9_DEVICE_RESET),
+        ));
+        ORIGINAL_PRESENT = Some(std::mem::transmute(
+            hook.get_original_method(D3D9_DEVICE_PRESENT),
+        ));
 
-        hook.replace_method(16, Self::hk_reset as usize);
-        hook.replace_method(17, Self::hk_present as usize);
+        hook.replace_method(D3D9_DEVICE_RESET, Self::hk_reset as usize);
+        self.hook_status.d3d9_reset = true;
+        hook.replace_method(D3D9_DEVICE_PRESENT, Self::hk_present as usize);
+        self.hook_status.d3d9_present = true;
 
         self.d3d9_hook = Some(hook);
     }
 
-    fn init_ui(&mut self) {
-        if let Some(device_hook) = &self.d3d9_hook {
-            let gui = EguiDx9::<Ui>::init(
-                device_hook.object(),
-                gta::get_window_handle(),
-                Ui::render_ui,
-                Ui::new(),
-                true,
-            );
+    // Other D3D9 hooks (ENB, ReShade, overlays) are expected to either wrap the device before us
+    // or to hook on top of us, in both cases our Present stays in the chain and is called.
+    // If a hook installed later restores or replaces the vtable without calling us, Present stops
+    // being called while the game is rendering, then we hook again on top of it and call it as the original.
+    pub fn verify_d3d9_hooks(&mut self) {
+        if self.last_hooks_check.elapsed() < Duration::from_secs(1) {
+            return;
+        }
+        self.last_hooks_check = Instant::now();
+
+        let present_calls = std::mem::take(&mut self.present_calls);
+        let device = match &self.d3d9_hook {
+            Some(hook) => hook.object().as_raw(),
+            None => return,
+        };
+
+        // Present is not called while minimized, that doesn't mean we were displaced.
+        if present_calls > 0 || unsafe { GetForegroundWindow() } != gta::get_window_handle() {
+            return;
+        }
+
+        let current_present = unsafe {
+            let vtable = *(device as *const *const usize);
+            *vtable.add(D3D9_DEVICE_PRESENT)
+        };
+        if current_present == Self::hk_present as usize {
+            return;
+        }
+
+        eprintln!("plugin::verify_d3d9_hooks: hooks were overwritten, installing again");
 
-            Ui::init_style(gui.ctx());
+        // Dropping the old hook would restore the vtable it has replaced and break the other hook.
+        if let Some(old_hook) = self.d3d9_hook.take() {
+            std::mem::forget(old_hook);
+        }
+        self.hook_status.d3d9_reset = false;
+        self.hook_status.d3d9_present = false;
 
-            self.gui = Some(gui);
+        unsafe {
+            self.install_d3d9_hooks();
+        }
+    }
+
+    /// Plugins that subclass the window after us normally call our procedure, but some replace it.
+    /// We are installed again only if the probe doesn't reach us, otherwise we would call ourselves.
+    pub fn verify_wnd_proc(&mut self) {
+        if self.last_wnd_proc_check.elapsed() < Duration::from_secs(2) {
+            return;
+        }
+        self.last_wnd_proc_check = Instant::now();
+
+        let probe = WND_PROC_PROBE.load(Ordering::Relaxed);
+        if !self.hook_status.wnd_proc || probe == 0 {
+            return;
+        }
+
+        unsafe {
+            let window = gta::get_window_handle();
+            if GetWindowLongPtrA(window, GWLP_WNDPROC) as usize == Self::hk_wnd_proc as usize {
+                return;
+            }
+
+            WND_PROC_PROBE_RECEIVED.store(false, Ordering::Relaxed);
+            SendMessageA(window, probe, WPARAM(0), LPARAM(0));
+            if WND_PROC_PROBE_RECEIVED.load(Ordering::Relaxed) {
+                return;
+            }
+
+            eprintln!("plugin::verify_wnd_proc: window procedure was replaced, installing again");
+            self.hook_status.wnd_proc = false;
+            self.install_wnd_proc();
+        }
+    }
+
+    fn init_ui(&mut self) {
+        if let Some(device_hook) = &self.d3d9_hook {
+            // The backend panics if it can't create its resources, e.g. on some GPUs.
+            // The game must keep working without our UI in that case.
+            let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                let gui = EguiDx9::<Ui>::init(
+                    device_hook.object(),
+                    gta::get_window_handle(),
+                    Ui::render_ui,
+                    Ui::new(),
+                    true,
+                );
+                Ui::init_style(gui.ctx());
+                gui
+            }));
+
+            match result {
+                Ok(gui) => {
+                    self.gui = Some(gui);
+                    self.hook_status.ui = true;
+                }
+                Err(_) => self.init_error("plugin::init_ui: failed to initialize egui".to_string()),
+            }
         }
     }
 
@@ -246,11 +1694,17 @@ impl Plugin {
         device: IDirect3DDevice9,
         presentation_parameters: *const D3DPRESENT_PARAMETERS,
     ) -> HRESULT {
+        let original_reset = ORIGINAL_RESET.unwrap_unchecked();
+        if IN_D3D9_HOOK.swap(true, Ordering::Acquire) {
+            return original_reset(device, presentation_parameters);
+        }
+
         let plugin = Plugin::get();
-        let gui = plugin.gui.as_mut().unwrap_unchecked();
-        gui.pre_reset();
+        if let Some(gui) = plugin.gui.as_mut() {
+            gui.pre_reset();
+        }
 
-        let original_reset = plugin.original_reset.unwrap_unchecked();
+        IN_D3D9_HOOK.store(false, Ordering::Release);
         original_reset(device, presentation_parameters)
     }
 
@@ -261,11 +1715,24 @@ impl Plugin {
         dest_window_override: HWND,
         dirty_region: *const RGNDATA,
     ) -> HRESULT {
+        let original_present = ORIGINAL_PRESENT.unwrap_unchecked();
+        if IN_D3D9_HOOK.swap(true, Ordering::Acquire) {
+            return original_present(
+                device,
+                source_rect,
+                dest_rect,
+                dest_window_override,
+                dirty_region,
+            );
+        }
+
         let plugin = Plugin::get();
-        let gui = plugin.gui.as_mut().unwrap_unchecked();
-        gui.present(&device);
+        plugin.present_calls = plugin.present_calls.wrapping_add(1);
+        if let Some(gui) = plugin.gui.as_mut() {
+            gui.present(&device);
+        }
 
-        let original_present = plugin.original_present.unwrap_unchecked();
+        IN_D3D9_HOOK.store(false, Ordering::Release);
         original_present(
             device,
             source_rect,
@@ -281,12 +1748,93 @@ impl Plugin {
         wparam: WPARAM,
         lparam: LPARAM,
     ) -> LRESULT {
+        let probe = WND_PROC_PROBE.load(Ordering::Relaxed);
+        if probe != 0 && msg == probe {
+            WND_PROC_PROBE_RECEIVED.store(true, Ordering::Relaxed);
+            return LRESULT(0);
+        }
+
         let plugin = Plugin::get();
-        let gui = plugin.gui.as_mut().unwrap_unchecked();
+        // Tracked even without the UI, and before it is borrowed.
+        match msg {
+            WM_ACTIVATE => plugin.set_window_focused((wparam.0 & 0xFFFF) as u32 != WA_INACTIVE),
+            WM_SETFOCUS => plugin.set_window_focused(true),
+            WM_KILLFOCUS => plugin.set_window_focused(false),
+            _ => {}
+        }
+
+        let gui = match plugin.gui.as_mut() {
+            Some(gui) => gui,
+            None => {
+                return CallWindowProcA(
+                    plugin.original_wnd_proc.unwrap_unchecked(),
+                    hwnd,
+                    msg,
+                    wparam,
+                    lparam,
+                )
+            }
+        };
         gui.wnd_proc(msg, wparam, lparam);
+        // The handlers below borrow the plugin, so take what is needed from egui first.
+        let wants_pointer_input = gui.ctx().wants_pointer_input();
+        let wants_keyboard_input = gui.ctx().wants_keyboard_input();
+
+        let is_mouse_msg = matches!(
+            msg,
+            WM_MOUSEMOVE
+                | WM_LBUTTONDOWN
+                | WM_LBUTTONUP
+                | WM_LBUTTONDBLCLK
+                | WM_RBUTTONDOWN
+                | WM_RBUTTONUP
+                | WM_MBUTTONDOWN
+                | WM_MBUTTONUP
+                | WM_MOUSEWHEEL
+        );
+
+        // Wheel messages have screen coordinates, so rely on the last move.
+        if is_mouse_msg && msg != WM_MOUSEWHEEL {
+            let x = (lparam.0 & 0xFFFF) as i16 as f32;
+            let y = ((lparam.0 >> 16) & 0xFFFF) as i16 as f32;
+            plugin.cursor_pos = Some(pos2(x, y));
+        }
 
-        if gui.ctx().wants_pointer_input() && (msg == WM_LBUTTONDOWN || msg == WM_MOUSEWHEEL) {
-            // To prevent the chat from closing when clicking on our interface.
+        if msg == WM_KEYDOWN {
+            let key = wparam.0 as u16;
+            plugin.swallowed_key = None;
+            if key == VK_RETURN.0 {
+                plugin.finish_template();
+            }
+            if plugin.handle_toggle_key(key)
+                || plugin.handle_navigation_key(key)
+                || plugin.handle_complete_key(key)
+                || (key == VK_RETURN.0 && plugin.handle_settings_command())
+                || (Self::is_bound(&plugin.config.keys.dismiss, key) && plugin.dismiss_list())
+            {
+                plugin.swallowed_key = Some(key);
+                return LRESULT(0);
+            }
+        }
+        // The character of a swallowed key, if it has one, comes right after it.
+        if msg == WM_CHAR && plugin.swallowed_key.take().is_some() {
+            return LRESULT(0);
+        }
+        if msg == WM_CHAR && !wants_keyboard_input && plugin.handle_template_char(wparam.0 as u8) {
+            return LRESULT(0);
+        }
+
+        let is_keyboard_msg = matches!(msg, WM_KEYDOWN | WM_KEYUP | WM_CHAR);
+
+        let consume = (is_mouse_msg
+            && (plugin.is_cursor_over_overlay()
+                || (wants_pointer_input && (msg == WM_LBUTTONDOWN || msg == WM_MOUSEWHEEL))))
+            // Typing into our text fields must not go to the chat.
+            || (is_keyboard_msg && wants_keyboard_input);
+
+        if consume {
+            // To prevent the chat from closing and the game from reacting
+            // when the cursor is on our interface.
             LRESULT(1)
         } else {
             CallWindowProcA(
@@ -301,30 +1849,44 @@ impl Plugin {
 
     fn get_samp_commands_grouped_by_module(&self) -> HashMap<String, Vec<String>> {
         let input = samp::Input::get().unwrap();
-        let cmd_count = input.command_count as usize;
-        let mut module_commands = HashMap::new();
-
-        if cmd_count > 0 {
-            let addresses = input.command_proc[..cmd_count].to_vec();
-            let module_names = utils::find_module_name_that_owns_address_list(&addresses).unwrap();
+        if input.command_count <= 0 {
+            return HashMap::new();
+        }
 
-            for (i, module_name) in module_names.iter().enumerate() {
-                let module_name = module_name.clone().unwrap_or("unknown".to_string());
+        // One snapshot for all commands.
+        let modules = utils::ModuleList::snapshot();
+        samp::group_commands_by_module(
+            &input.command_proc,
+            &input.command_name,
+            input.command_count,
+            |address| modules.as_ref().and_then(|modules| modules.find(address)),
+        )
+    }
 
-                let cmd = if let Ok(cstr) = CStr::from_bytes_until_nul(&input.command_name[i]) {
-                    cstr.to_string_lossy().to_string()
-                } else {
-                    "unknown".to_string()
-                };
+    fn get_samp_command_handlers(&self) -> HashMap<String, SampHandler> {
+        let input = samp::Input::get().unwrap();
+        let count = (input.command_count.max(0) as usize).min(samp::MAX_CLIENT_CMDS);
+        let handlers = &input.command_proc[..count];
+        let mut owners = utils::find_module_name_that_owns_address_list_with_base(handlers)
+            .unwrap_or_else(|| vec![None; count]);
+        (0..count)
+            .filter_map(|i| {
+                let cmd = input.command_name(i)?;
+                let address = handlers[i] as usize;
+                let location = owners[i].take().map(|(name, base)| (name, address - base));
+                Some((cmd_with_prefix(&cmd), SampHandler { address, location }))
+            })
+            .collect()
+    }
 
-                module_commands
-                    .entry(module_name)
-                    .or_insert(Vec::new())
-                    .push(cmd);
-            }
-        }
+    pub fn samp_handler_address(&self, command: &str) -> Option<usize> {
+        self.samp_handlers.get(command).map(|h| h.address)
+    }
 
-        module_commands
+    /// Module of the handler and its offset there, stays the same between game launches.
+    pub fn samp_handler_location(&self, command: &str) -> Option<(&str, usize)> {
+        let (module, offset) = self.samp_handlers.get(command)?.location.as_ref()?;
+        Some((module, *offset))
     }
 
     fn get_sampfuncs_commands_grouped(
@@ -337,18 +1899,12 @@ impl Plugin {
         let sf_cmds = sampfuncs::SampFuncs::get_chat_commands();
         let mut commands = HashMap::new();
 
-        for cmd in &sf_cmds {
-            let owner_name = match cmd.owner() {
-                CmdOwner::Nope => "unknown".to_string(),
-                CmdOwner::Script(s) => s.thread_name().trim_end().to_string() + ".cs",
-                CmdOwner::Plugin(p) => p.plugin_name(),
-            };
-
+        for cmd in sf_cmds {
             commands
-                .entry(owner_name)
+                .entry(cmd.owner_name)
                 .or_insert((cmd.owner_type, Vec::new()))
                 .1
-                .push(cmd.name.to_string());
+                .push(cmd.name);
         }
 
         Some(commands)
@@ -360,19 +1916,55 @@ unsafe fn initialize_plugin() {
 
     match STATE {
         InitState::BeforeSampInit => {
+            let plugin = Plugin::get();
+
+            // Patched from the game thread rather than DllMain, so SA-MP can't be running the code.
+            // Falls back to reading the commands from CInput once it is ready.
+            if !samp::is_ready(plugin.samp_base_address, plugin.samp_version) {
+                if let Err(e) =
+                    samp::install_add_command_hook(plugin.samp_base_address, plugin.samp_version)
+                {
+                    eprintln!("samp::install_add_command_hook: {}", e);
+                }
+            }
+
             STATE = InitState::AfterSampInit;
         }
         InitState::AfterSampInit => {
             let plugin = Plugin::get();
 
-            samp::initialize(plugin.samp_base_address, plugin.samp_version);
+            // Checked every cycle until SA-MP has created its structures.
+            if !samp::is_ready(plugin.samp_base_address, plugin.samp_version) {
+                return;
+            }
 
-            // We can work without this module.
-            if let Err(e) = sampfuncs::initialize() {
-                eprintln!("sampfuncs::initialize: {}", e);
+            // Without valid SA-MP structures there is nothing to show.
+            if let Err(e) = samp::initialize(plugin.samp_base_address, plugin.samp_version) {
+                plugin.init_error(format!("samp::initialize: {}", e));
+                plugin.write_init_report();
+                STATE = InitState::Failed;
+                return;
             }
+            plugin.apply_chat_hook_config();
 
-            plugin.post_initialize();
+            // We can work without this module.
+            match sampfuncs::initialize() {
+                Ok(()) | Err(Error::ModuleNotLoaded(_)) => {}
+                Err(e) => eprintln!("sampfuncs::initialize: {}", e),
+            }
+            plugin.integration_retry_until = Some(Instant::now() + INTEGRATION_RETRY_TIME);
+
+            // A panic here must not take the game down, but it is worth a report.
+            let result = std::panic::catch_unwind(AssertUnwindSafe(|| plugin.post_initialize()));
+            if let Err(payload) = result {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .map(|s| s.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_default();
+                plugin.init_error(format!("plugin::post_initialize: panicked: {}", message));
+            }
+            plugin.write_init_report();
 
             STATE = InitState::Initialized;
         }
@@ -384,12 +1976,44 @@ unsafe fn initialize_plugin() {
             if time.elapsed().unwrap() > Duration::from_secs(3) {
                 let plugin = Plugin::get();
                 plugin.parse_commands();
+                plugin.apply_ipc_config();
 
                 STATE = InitState::Nothing;
             }
         }
-        InitState::Nothing => {}
+        InitState::Nothing => {
+            let plugin = Plugin::get();
+            plugin.update_server();
+            plugin.reload_changed_config();
+            plugin.verify_d3d9_hooks();
+            plugin.verify_wnd_proc();
+            plugin.apply_pending_sources();
+            plugin.apply_registered_commands();
+            plugin.apply_pending_chat_text();
+            plugin.retry_integrations();
+        }
+        InitState::Failed => {}
+    }
+}
+
+// Lines are oldest first, the suggestions are the most recent first.
+fn suggest_from_chat(
+    lines: &[String],
+    parser: ChatParser,
+    is_registered: impl Fn(&str) -> bool,
+) -> Vec<String> {
+    let mut suggestions = Vec::new();
+    for line in lines.iter().rev() {
+        for command in parser(line) {
+            if suggestions.len() == MAX_CHAT_SUGGESTIONS {
+                return suggestions;
+            }
+            if is_registered(&command) && !suggestions.contains(&command) {
+                suggestions.push(command);
+            }
+        }
     }
+    suggestions
 }
 
 // This function is called cyclically in the game.
@@ -398,7 +2022,7 @@ unsafe extern "C" fn hk_defined_state() {
     FUNC_GTA_DEFINED_STATE.unwrap()();
 }
 
-pub fn initialize() -> Result<(), Error> {
+pub fn initialize(instance: HMODULE) -> Result<(), Error> {
     const ADDRESS_OF_CALL_DEFINED_STATE_IN_IDLE: usize = 0x53EA8E;
 
     let current_byte = unsafe { *(ADDRESS_OF_CALL_DEFINED_STATE_IN_IDLE as *const u8) };
@@ -414,12 +2038,17 @@ pub fn initialize() -> Result<(), Error> {
 
     match samp::get_version(samp_base_address) {
         Some(samp_version) => unsafe {
-            PLUGIN = Some(Plugin::new(samp_base_address, samp_version));
+            let data_dir = utils::get_module_directory(instance)
+                .unwrap_or_default()
+                .join(config::DATA_DIRECTORY);
+            PLUGIN = Some(Plugin::new(samp_base_address, samp_version, data_dir));
+            Plugin::get().apply_code_page_config();
 
             // We can work without this module.
             // Hooks must be installed before ML starts loading scripts.
-            if let Err(e) = moonloader::initialize() {
-                eprintln!("moonloader::initialize: {}", e);
+            match moonloader::initialize() {
+                Ok(()) | Err(Error::ModuleNotLoaded(_)) => {}
+                Err(e) => eprintln!("moonloader::initialize: {}", e),
             }
 
             FUNC_GTA_DEFINED_STATE = Some(std::mem::transmute(utils::extract_call_target_address(
@@ -428,10 +2057,99 @@ pub fn initialize() -> Result<(), Error> {
             utils::patch_call_address(
                 ADDRESS_OF_CALL_DEFINED_STATE_IN_IDLE,
                 hk_defined_state as usize,
-            );
+            )?;
+            Plugin::get().hook_status.defined_state = true;
 
             Ok(())
         },
         None => Err(Error::IncompatibleSampVersion),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn give() -> TemplateEdit {
+        TemplateEdit::new("/give", vec!["playerid".to_string(), "amount".to_string()])
+    }
+
+    fn type_str(edit: &mut TemplateEdit, text: &str) {
+        text.chars().for_each(|c| edit.type_char(c));
+    }
+
+    #[test]
+    fn template_starts_with_placeholders() {
+        let edit = give();
+        assert_eq!(edit.text(), "/give {playerid} {amount}");
+        assert_eq!(edit.filled_text(), "/give");
+        assert_eq!(edit.current, 0);
+    }
+
+    #[test]
+    fn typing_replaces_the_current_placeholder() {
+        let mut edit = give();
+        type_str(&mut edit, "12");
+        assert_eq!(edit.text(), "/give 12 {amount}");
+
+        assert!(edit.next());
+        type_str(&mut edit, "500");
+        assert_eq!(edit.text(), "/give 12 500");
+        assert!(!edit.next());
+    }
+
+    #[test]
+    fn space_moves_to_the_next_placeholder_except_in_the_last_one() {
+        let mut edit = TemplateEdit::new("/pm", vec!["id".to_string(), "text".to_string()]);
+        type_str(&mut edit, "3 hello there");
+        assert_eq!(edit.current, 1);
+        assert_eq!(edit.text(), "/pm 3 hello there");
+    }
+
+    #[test]
+    fn backspace_goes_back_to_the_previous_placeholder() {
+        let mut edit = give();
+        type_str(&mut edit, "1 2");
+        assert!(edit.backspace());
+        assert_eq!(edit.text(), "/give 1 {amount}");
+        assert!(edit.backspace());
+        assert_eq!(edit.current, 0);
+        assert!(edit.backspace());
+        assert_eq!(edit.text(), "/give {playerid} {amount}");
+        assert!(!edit.backspace());
+    }
+
+    #[test]
+    fn unfilled_placeholders_are_not_sent() {
+        let mut edit = give();
+        type_str(&mut edit, "7");
+        assert_eq!(edit.filled_text(), "/give 7");
+    }
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn chat_suggestions_use_the_default_parser() {
+        let chat = lines(&["Type /help for help", "Use /car or /help"]);
+        let suggestions = suggest_from_chat(&chat, cmd_storage::extract_commands, |c| c != "/car");
+        assert_eq!(suggestions, vec!["/help".to_string()]);
+    }
+
+    #[test]
+    fn chat_suggestions_use_a_custom_parser() {
+        // Server that writes commands without the prefix in brackets.
+        fn bracketed(text: &str) -> Vec<String> {
+            text.split('[')
+                .skip(1)
+                .filter_map(|part| part.split(']').next())
+                .map(|name| format!("/{}", name))
+                .collect()
+        }
+
+        let chat = lines(&["Type [help] for help", "Use [car] or /menu"]);
+        let suggestions = suggest_from_chat(&chat, bracketed, |_| true);
+        assert_eq!(suggestions, vec!["/car".to_string(), "/help".to_string()]);
+    }
+}
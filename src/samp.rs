@@ -9,13 +9,34 @@
  *
  *****************************************************************************/
 
+use std::collections::{HashMap, VecDeque};
 use std::ffi::{c_char, c_void, CStr, CString};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use windows::Win32::{Foundation::BOOL, Graphics::Direct3D9::IDirect3DDevice9};
 
-use crate::utils::get_entry_point;
+use crate::errors::Error;
+use crate::plugin::Plugin;
+use crate::utils::{self, get_entry_point};
+
+type ChatAddMessage = extern "thiscall" fn(*mut c_void, u32, *const c_char);
+type InputAddCommand = extern "thiscall" fn(*mut Input, *const c_char, *const c_void);
+type InputOpen = extern "thiscall" fn(*mut Input);
 
 static mut INPUT: Option<*mut Input> = None;
+static mut NET_GAME: Option<*const *const u8> = None;
+static mut VERSION: Option<Version> = None;
+static mut BASE_ADDRESS: Option<usize> = None;
+static mut CHAT_ADD_MESSAGE: Option<ChatAddMessage> = None;
+static mut INPUT_ADD_COMMAND: Option<InputAddCommand> = None;
+// A call made while another one is handled, e.g. from another thread, is only passed to SA-MP,
+// the plugin must not be entered twice.
+static IN_ADD_COMMAND_HOOK: AtomicBool = AtomicBool::new(false);
+static mut INPUT_OPEN: Option<InputOpen> = None;
+static mut RECENT_CHAT_LINES: VecDeque<String> = VecDeque::new();
+
+// As many as the chat itself keeps.
+const MAX_RECENT_CHAT_LINES: usize = 100;
 static mut DXUT_EDIT_BOX_GET_TEXT: Option<DxutEditBoxGetText> = None;
 static mut DXUT_EDIT_BOX_SET_TEXT: Option<DxutEditBoxSetText> = None;
 
@@ -45,16 +66,48 @@ pub fn get_version(base_address: usize) -> Option<Version> {
     }
 }
 
-pub fn initialize(base_address: usize, version: Version) {
+/// SA-MP creates CInput some time after being loaded, its structures must not be read before that.
+pub fn is_ready(base_address: usize, version: Version) -> bool {
+    let input_address = base_address + get_input_offset(version);
+    if !utils::is_readable_address(input_address) {
+        return false;
+    }
+
+    let input = unsafe { *(input_address as *const *const Input) };
+    if input.is_null() || !utils::is_readable_address(input as usize) {
+        return false;
+    }
+
+    let command_count =
+        unsafe { std::ptr::read_unaligned(std::ptr::addr_of!((*input).command_count)) };
+    (0..=MAX_CLIENT_CMDS as i32).contains(&command_count)
+}
+
+/// The version is checked again, since the module may have been patched since it was detected.
+pub fn initialize(base_address: usize, version: Version) -> Result<(), Error> {
+    if get_version(base_address) != Some(version) {
+        return Err(Error::IncompatibleSampVersion);
+    }
+    if !is_ready(base_address, version) {
+        return Err(Error::MaybeInvalidGameOrPluginConflicting);
+    }
+
     unsafe {
         INPUT = Some(*((base_address + get_input_offset(version)) as *mut *mut Input));
+        // CNetGame is created only when connecting, so store the address of the pointer.
+        NET_GAME = Some((base_address + get_net_game_offset(version)) as *const *const u8);
+        VERSION = Some(version);
+        BASE_ADDRESS = Some(base_address);
         DXUT_EDIT_BOX_GET_TEXT = Some(std::mem::transmute(
             base_address + get_offset_of_dxut_edit_box_get_text(version),
         ));
         DXUT_EDIT_BOX_SET_TEXT = Some(std::mem::transmute(
             base_address + get_offset_of_dxut_edit_box_set_text(version),
         ));
+        INPUT_OPEN = get_offset_of_input_open(version)
+            .map(|offset| std::mem::transmute(base_address + offset));
     }
+    Ok(())
 }
 
 fn get_input_offset(version: Version) -> usize {
@@ -68,6 +121,80 @@ fn get_input_offset(version: Version) -> usize {
     }
 }
 
+fn get_net_game_offset(version: Version) -> usize {
+    match version {
+        Version::V037R1 => 0x21A0F8,
+        Version::V037R2 => 0x21A100,
+        Version::V037R3 | Version::V037R3_1 => 0x26E8DC,
+        Version::V037R4 | Version::V037R4_2 => 0x26EA0C,
+        Version::V037R5 => 0x26EB94,
+        Version::V03DLR1 => 0x2ACA24,
+    }
+}
+
+struct NetGameLayout {
+    host_address: usize,
+    port: usize,
+    game_state: usize,
+}
+
+// CNetGame fields, from the SAMP-API headers: sampapi/0.3.7-R1 for R1, 0.3.7-R3-1 for R3 and R3-1,
+// 0.3.7-R5-1 for R5. R2 and R4 are not there and 0.3.DL differs, so they are not guessed.
+fn get_net_game_layout(version: Version) -> Result<NetGameLayout, Error> {
+    match version {
+        Version::V037R1 => Ok(NetGameLayout {
+            host_address: 0x20,
+            port: 0x225,
+            game_state: 0x3BD,
+        }),
+        Version::V037R3 | Version::V037R3_1 | Version::V037R5 => Ok(NetGameLayout {
+            host_address: 0x30,
+            port: 0x235,
+            game_state: 0x3CD,
+        }),
+        Version::V037R2 | Version::V037R4 | Version::V037R4_2 | Version::V03DLR1 => {
+            Err(Error::UnknownOffset("CNetGame"))
+        }
+    }
+}
+
+// CChat::AddMessage, used for messages sent by the server.
+fn get_offset_of_chat_add_message(version: Version) -> usize {
+    match version {
+        Version::V037R1 => 0x645A0,
+        Version::V037R2 => 0x64670,
+        Version::V037R3 | Version::V037R3_1 => 0x679F0,
+        Version::V037R4 => 0x68130,
+        Version::V037R4_2 => 0x68170,
+        Version::V037R5 => 0x68170,
+        Version::V03DLR1 => 0x67BE0,
+    }
+}
+
+// CInput::AddCommand, not known for 0.3.DL yet.
+fn get_offset_of_input_add_command(version: Version) -> Option<usize> {
+    match version {
+        Version::V037R1 => Some(0x65AD0),
+        Version::V037R2 => Some(0x65BA0),
+        Version::V037R3 | Version::V037R3_1 => Some(0x69000),
+        Version::V037R4 => Some(0x69730),
+        Version::V037R4_2 | Version::V037R5 => Some(0x69770),
+        Version::V03DLR1 => None,
+    }
+}
+
+// CInput::Open, not known for 0.3.DL yet.
+fn get_offset_of_input_open(version: Version) -> Option<usize> {
+    match version {
+        Version::V037R1 => Some(0x657E0),
+        Version::V037R2 => Some(0x658B0),
+        Version::V037R3 | Version::V037R3_1 => Some(0x68D10),
+        Version::V037R4 => Some(0x69440),
+        Version::V037R4_2 | Version::V037R5 => Some(0x69480),
+        Version::V03DLR1 => None,
+    }
+}
+
 fn get_offset_of_dxut_edit_box_get_text(version: Version) -> usize {
     match version {
         Version::V037R1 => 0x81030,
@@ -92,6 +219,184 @@ fn get_offset_of_dxut_edit_box_set_text(version: Version) -> usize {
     }
 }
 
+// Flag of CNetGame telling whether client commands are processed, as a byte.
+// No version has a verified offset yet, the state is unknown for them.
+fn get_commands_enabled_offset(version: Version) -> Option<usize> {
+    match version {
+        Version::V037R1
+        | Version::V037R2
+        | Version::V037R3
+        | Version::V037R3_1
+        | Version::V037R4
+        | Version::V037R4_2
+        | Version::V037R5
+        | Version::V03DLR1 => None,
+    }
+}
+
+/// Opens the chat with the text typed in, or replaces the text if it is already open.
+pub fn open_chat_with(text: &str) -> bool {
+    match Input::get() {
+        Some(input) => input.open_with(text),
+        None => false,
+    }
+}
+
+/// Whether the server processes client commands. None if it can't be known,
+/// e.g. the offset of the flag is unknown for the version or there is no connection.
+pub fn are_commands_enabled() -> Option<bool> {
+    unsafe {
+        let offset = get_commands_enabled_offset(VERSION?)?;
+        let net_game = *NET_GAME?;
+        if net_game.is_null() || !utils::is_readable_address(net_game as usize + offset) {
+            return None;
+        }
+        Some(*net_game.add(offset) != 0)
+    }
+}
+
+const GAME_STATE_CONNECTED: i32 = 5;
+const MAX_HOST_ADDRESS: usize = 256;
+
+/// Returns "host:port" of the server we are connected to.
+/// None for versions without a known CNetGame layout, the server descriptions are not loaded there.
+pub fn get_server_address() -> Option<String> {
+    unsafe {
+        let net_game = *NET_GAME?;
+        if net_game.is_null() {
+            return None;
+        }
+
+        let layout = get_net_game_layout(VERSION?).ok()?;
+
+        // Freed or not yet allocated CNetGame reads as nothing rather than crashes.
+        let readable = utils::readable_size(net_game as usize);
+        let int_size = std::mem::size_of::<i32>();
+        if readable < layout.game_state + int_size
+            || readable < layout.port + int_size
+            || readable <= layout.host_address
+        {
+            return None;
+        }
+
+        let game_state = std::ptr::read_unaligned(net_game.add(layout.game_state) as *const i32);
+        if game_state != GAME_STATE_CONNECTED {
+            return None;
+        }
+
+        // The host is nul-terminated, but the buffer may be the last thing in the region.
+        let host_size = (readable - layout.host_address).min(MAX_HOST_ADDRESS + 1);
+        let host = std::slice::from_raw_parts(net_game.add(layout.host_address), host_size);
+        let host = utils::decode_ansi(host);
+        let port = std::ptr::read_unaligned(net_game.add(layout.port) as *const i32);
+
+        Some(format!("{}:{}", host, port))
+    }
+}
+
+/// Redirects the calls of CChat::AddMessage inside samp.dll to let the plugin see chat messages.
+/// Calls made by other modules are not affected. Does nothing if already installed.
+pub fn install_chat_hook() -> Result<(), Error> {
+    unsafe {
+        if CHAT_ADD_MESSAGE.is_some() {
+            return Ok(());
+        }
+
+        let (base_address, version) = match (BASE_ADDRESS, VERSION) {
+            (Some(base_address), Some(version)) => (base_address, version),
+            _ => return Err(Error::IncompatibleSampVersion),
+        };
+
+        let add_message = base_address + get_offset_of_chat_add_message(version);
+        let call_sites = utils::find_call_sites(base_address, add_message);
+        if call_sites.is_empty() {
+            return Err(Error::CallSitesNotFound("CChat::AddMessage"));
+        }
+
+        CHAT_ADD_MESSAGE = Some(std::mem::transmute(add_message));
+        // Patched sites call the original through the hook, so a failure leaves them working.
+        for address in call_sites {
+            utils::patch_call_address(address, hk_chat_add_message as usize)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Hooks CInput::AddCommand to see commands as any module registers them.
+/// Must be installed before CInput is created, since commands registered earlier are not seen.
+/// Installed from the game thread, SA-MP registers its commands on it as well.
+/// Without it the commands are read from CInput once it is ready.
+pub fn install_add_command_hook(base_address: usize, version: Version) -> Result<(), Error> {
+    unsafe {
+        if INPUT_ADD_COMMAND.is_some() {
+            return Ok(());
+        }
+
+        let offset = get_offset_of_input_add_command(version)
+            .ok_or(Error::UnknownOffset("CInput::AddCommand"))?;
+        let trampoline =
+            utils::hook_function(base_address + offset, hk_input_add_command as usize)?;
+        INPUT_ADD_COMMAND = Some(std::mem::transmute(trampoline));
+
+        Ok(())
+    }
+}
+
+pub fn is_add_command_hooked() -> bool {
+    unsafe { INPUT_ADD_COMMAND.is_some() }
+}
+
+extern "thiscall" fn hk_input_add_command(
+    input: *mut Input,
+    name: *const c_char,
+    handler: *const c_void,
+) {
+    unsafe { INPUT_ADD_COMMAND.unwrap_unchecked()(input, name, handler) };
+    if IN_ADD_COMMAND_HOOK.swap(true, Ordering::Acquire) {
+        return;
+    }
+
+    // SA-MP ignores commands over the limit and truncates long names, so take what it has stored.
+    let input = unsafe { &*input };
+    let count = (input.command_count.max(0) as usize).min(MAX_CLIENT_CMDS);
+    if let Some(index) = (0..count).rev().find(|&i| input.command_proc[i] == handler) {
+        if let Some(command) = input.command_name(index) {
+            Plugin::get().add_samp_command(&command, handler as usize);
+        }
+    }
+
+    IN_ADD_COMMAND_HOOK.store(false, Ordering::Release);
+}
+
+/// Last `count` messages added to the chat since the chat hook was installed, oldest first.
+pub fn get_recent_chat_lines(count: usize) -> Vec<String> {
+    unsafe {
+        let lines = &*std::ptr::addr_of!(RECENT_CHAT_LINES);
+        lines
+            .iter()
+            .skip(lines.len().saturating_sub(count))
+            .cloned()
+            .collect()
+    }
+}
+
+extern "thiscall" fn hk_chat_add_message(chat: *mut c_void, color: u32, text: *const c_char) {
+    let message = unsafe { utils::decode_ansi_ptr(text) };
+    if !message.is_empty() {
+        unsafe {
+            let lines = &mut *std::ptr::addr_of_mut!(RECENT_CHAT_LINES);
+            if lines.len() == MAX_RECENT_CHAT_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(message.clone());
+        }
+        Plugin::get().handle_chat_message(&message);
+    }
+
+    unsafe { CHAT_ADD_MESSAGE.unwrap_unchecked()(chat, color, text) }
+}
+
 pub const MAX_CLIENT_CMDS: usize = 144;
 pub const MAX_CMD_LENGTH: usize = 32;
 pub const MAX_CHAT_INPUT: usize = 128;
@@ -114,19 +419,110 @@ pub struct Input {
     pub default_proc: *const u8,
 }
 
+// CInput has the same layout in all supported versions, including 0.3.DL,
+// which extended other chat structures but not this one.
+#[cfg(target_pointer_width = "32")]
+const _: () = {
+    use std::mem::{offset_of, size_of};
+    assert!(offset_of!(Input, command_proc) == 0xC);
+    assert!(offset_of!(Input, command_name) == 0x24C);
+    assert!(offset_of!(Input, command_count) == 0x14DC);
+    assert!(offset_of!(Input, enabled) == 0x14E0);
+    assert!(offset_of!(Input, input) == 0x14E4);
+    assert!(offset_of!(Input, recall_buffer) == 0x1565);
+    assert!(offset_of!(Input, current_buffer) == 0x1A6F);
+    assert!(offset_of!(Input, current_recall) == 0x1AF0);
+    assert!(offset_of!(Input, total_recall) == 0x1AF4);
+    assert!(offset_of!(Input, default_proc) == 0x1AF8);
+    assert!(size_of::<Input>() == 0x1AFC);
+};
+
 impl Input {
     pub fn get<'a>() -> Option<&'a mut Input> {
         unsafe {
             match INPUT {
-                Some(v) => Some(&mut *v),
-                None => None,
+                Some(v) if !v.is_null() => Some(&mut *v),
+                _ => None,
+            }
+        }
+    }
+
+    /// Opens the chat input as if the user has pressed the key, false if it isn't possible.
+    pub fn open(&mut self) -> bool {
+        match unsafe { INPUT_OPEN } {
+            Some(open) => {
+                open(self as *mut Self);
+                true
             }
+            None => false,
         }
     }
 
+    /// Puts the text into the chat input, opening it first if it is closed.
+    pub fn open_with(&mut self, text: &str) -> bool {
+        if !self.enabled.as_bool() && !self.open() {
+            return false;
+        }
+        self.edit_box().set_text(text)
+    }
+
     pub fn edit_box(&self) -> &mut DXUTEditBox {
         unsafe { &mut *self.edit_box }
     }
+
+    pub fn command_name(&self, index: usize) -> Option<String> {
+        parse_command_name(&self.command_name[index])
+    }
+}
+
+/// Commands registered with a name of the maximum length may be left without a nul terminator,
+/// so in that case the name is cut at the first non-printable byte instead.
+/// Names are in the ANSI code page in every version: 0.3.DL uses UTF-16 only in its chat
+/// rendering, `AddCmdProc` still copies the name into `char[33]`.
+pub fn parse_command_name(buffer: &[u8]) -> Option<String> {
+    let name = match CStr::from_bytes_until_nul(buffer) {
+        Ok(cstr) => cstr.to_bytes(),
+        Err(_) => {
+            let buffer = &buffer[..buffer.len().min(MAX_CMD_LENGTH)];
+            let end = buffer
+                .iter()
+                .position(|&b| b < 0x20 || b == 0x7F)
+                .unwrap_or(buffer.len());
+            &buffer[..end]
+        }
+    };
+
+    if name.is_empty() {
+        None
+    } else {
+        Some(utils::decode_ansi(name))
+    }
+}
+
+/// Groups the first `count` commands by the module that owns their handler.
+/// Doesn't read SA-MP memory by itself, so it works with any buffers.
+pub fn group_commands_by_module<'a>(
+    procs: &[*const c_void],
+    names: &[[u8; MAX_CMD_LENGTH + 1]],
+    count: i32,
+    find_module: impl Fn(*const c_void) -> Option<&'a str>,
+) -> HashMap<String, Vec<String>> {
+    let count = (count.max(0) as usize).min(procs.len()).min(names.len());
+    let mut module_commands: HashMap<String, Vec<String>> = HashMap::new();
+
+    for (&address, name) in procs[..count].iter().zip(&names[..count]) {
+        let module_name = find_module(address).unwrap_or("unknown");
+        let cmd = parse_command_name(name).unwrap_or_else(|| "unknown".to_string());
+
+        match module_commands.get_mut(module_name) {
+            Some(cmds) => cmds.push(cmd),
+            None => {
+                module_commands.insert(module_name.to_string(), vec![cmd]);
+            }
+        }
+    }
+
+    module_commands
 }
 
 #[repr(C, align(1))]
@@ -141,21 +537,135 @@ type DxutEditBoxGetText = extern "thiscall" fn(*const DXUTEditBox) -> *const c_c
 type DxutEditBoxSetText = extern "thiscall" fn(*mut DXUTEditBox, *const c_char, bool);
 
 impl DXUTEditBox {
-    pub fn set_text_raw(&mut self, text: *const c_char) {
-        let func = unsafe { DXUT_EDIT_BOX_SET_TEXT.unwrap() };
-        func(self as *mut Self, text, false)
+    /// False if SA-MP has not been initialized, the text is not changed then.
+    pub fn set_text_raw(&mut self, text: *const c_char) -> bool {
+        match unsafe { DXUT_EDIT_BOX_SET_TEXT } {
+            Some(func) => {
+                func(self as *mut Self, text, false);
+                true
+            }
+            None => {
+                eprintln!("samp::DXUTEditBox::set_text_raw: SA-MP is not initialized");
+                false
+            }
+        }
     }
 
+    /// Empty if SA-MP has not been initialized.
     pub fn get_text<'a>(&self) -> String {
         unsafe {
-            let func = DXUT_EDIT_BOX_GET_TEXT.unwrap();
-            let c_str = func(self as *const Self);
-            CStr::from_ptr(c_str).to_string_lossy().to_string()
+            match DXUT_EDIT_BOX_GET_TEXT {
+                Some(func) => utils::decode_ansi_ptr(func(self as *const Self)),
+                None => String::new(),
+            }
         }
     }
 
-    pub fn set_text(&mut self, text: &str) {
-        let c_str = CString::new(text).unwrap();
-        self.set_text_raw(c_str.as_ptr());
+    pub fn set_text(&mut self, text: &str) -> bool {
+        // Text with a nul inside can't be passed, it is cleared then.
+        let c_str = CString::new(utils::encode_ansi(text)).unwrap_or_default();
+        self.set_text_raw(c_str.as_ptr())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name_buffer(name: &[u8]) -> [u8; MAX_CMD_LENGTH + 1] {
+        let mut buffer = [0u8; MAX_CMD_LENGTH + 1];
+        buffer[..name.len()].copy_from_slice(name);
+        buffer
+    }
+
+    #[test]
+    fn empty_name_is_skipped() {
+        assert_eq!(parse_command_name(&name_buffer(b"")), None);
+        assert_eq!(parse_command_name(&[]), None);
+    }
+
+    #[test]
+    fn name_ends_at_the_nul() {
+        let buffer = name_buffer(b"help\0junk");
+        assert_eq!(parse_command_name(&buffer).as_deref(), Some("help"));
+    }
+
+    #[test]
+    fn full_buffer_keeps_the_whole_name() {
+        let name = [b'a'; MAX_CMD_LENGTH];
+        let parsed = parse_command_name(&name_buffer(&name)).unwrap();
+        assert_eq!(parsed.len(), MAX_CMD_LENGTH);
+    }
+
+    #[test]
+    fn unterminated_buffer_is_cut() {
+        // The terminator of the maximum length has been overwritten.
+        let parsed = parse_command_name(&[b'b'; MAX_CMD_LENGTH + 1]).unwrap();
+        assert_eq!(parsed, "b".repeat(MAX_CMD_LENGTH));
+
+        let mut buffer = [b'c'; MAX_CMD_LENGTH + 1];
+        buffer[3] = 0x01;
+        assert_eq!(parse_command_name(&buffer).as_deref(), Some("ccc"));
+    }
+
+    // A chat input with the commands registered as SA-MP does it, the handlers are fake addresses.
+    fn fake_input(commands: &[(&str, usize)]) -> Box<Input> {
+        let mut input: Box<Input> = Box::new(unsafe { std::mem::zeroed() });
+        for (i, &(name, handler)) in commands.iter().enumerate() {
+            input.command_proc[i] = handler as *const c_void;
+            input.command_name[i] = name_buffer(name.as_bytes());
+        }
+        input.command_count = commands.len() as i32;
+        input
+    }
+
+    // Handlers below 0x1000 belong to "samp.dll", up to 0x2000 to "plugin.asi".
+    fn group(input: &Input) -> HashMap<String, Vec<String>> {
+        group_commands_by_module(
+            &input.command_proc,
+            &input.command_name,
+            input.command_count,
+            |address| match address as usize {
+                0..=0xFFF => Some("samp.dll"),
+                0x1000..=0x1FFF => Some("plugin.asi"),
+                _ => None,
+            },
+        )
+    }
+
+    #[test]
+    fn commands_are_grouped_by_module() {
+        let input = fake_input(&[
+            ("pagesize", 0x10),
+            ("tp", 0x1010),
+            ("timestamp", 0x20),
+            ("hidden", 0x5000),
+        ]);
+        let groups = group(&input);
+        assert_eq!(groups["samp.dll"], ["pagesize", "timestamp"]);
+        assert_eq!(groups["plugin.asi"], ["tp"]);
+        assert_eq!(groups["unknown"], ["hidden"]);
+    }
+
+    #[test]
+    fn empty_names_are_unknown() {
+        let input = fake_input(&[("", 0x10)]);
+        assert_eq!(group(&input)["samp.dll"], ["unknown"]);
+    }
+
+    #[test]
+    fn count_is_clamped() {
+        let mut input = fake_input(&[("a", 0x10), ("b", 0x10)]);
+        input.command_count = -1;
+        assert!(group(&input).is_empty());
+
+        input.command_count = 1;
+        assert_eq!(group(&input)["samp.dll"], ["a"]);
+
+        // The rest of the array is empty, but must not be read past its end.
+        input.command_count = i32::MAX;
+        let groups = group(&input);
+        assert_eq!(groups["samp.dll"].len(), MAX_CLIENT_CMDS);
+        assert_eq!(groups["samp.dll"][..2], ["a", "b"]);
     }
 }
@@ -10,13 +10,11 @@
  *****************************************************************************/
 
 use std::ffi::c_void;
-use windows::{
-    core::{s, w},
-    Win32::System::LibraryLoader::{GetModuleHandleW, GetProcAddress},
-};
+use windows::{core::s, Win32::System::LibraryLoader::GetProcAddress};
 
-use crate::cppstd::{StdString, StdVector};
+use crate::cppstd::{self, CrtFree, StdString, StdVector};
 use crate::errors::Error;
+use crate::utils;
 
 #[repr(C)]
 pub struct CommandInfo {
@@ -58,7 +56,7 @@ pub struct ScmThread {
 impl ScmThread {
     pub fn thread_name(&self) -> String {
         let get_scm_thread_name = unsafe { GET_SCM_THREAD_NAME.unwrap() };
-        get_scm_thread_name(self).to_string()
+        take_string(get_scm_thread_name(self))
     }
 }
 
@@ -80,11 +78,25 @@ pub struct SfPluginInfo {
 impl SfPluginInfo {
     pub fn plugin_name(&self) -> String {
         let get_plugin_name = unsafe { GET_PLUGIN_NAME.unwrap() };
-        get_plugin_name(self).to_string()
+        take_string(get_plugin_name(self))
     }
 }
 
 static mut INITIALIZED: bool = false;
+static mut FAILED: bool = false;
+
+// Containers returned by value are allocated by SAMPFUNCS, so they must be freed by its CRT.
+// Found only if SAMPFUNCS links the MSVC CRT dynamically, otherwise they are leaked on purpose:
+// a leak is better than freeing them with an allocator that didn't make them.
+static mut CRT_FREE: Option<CrtFree> = None;
+
+fn take_string(string: StdString) -> String {
+    let result = string.to_string();
+    if let Some(free) = unsafe { CRT_FREE } {
+        unsafe { string.free_buffer(free) };
+    }
+    result
+}
 
 static mut GET_CHAT_COMMANDS: Option<extern "thiscall" fn() -> StdVector<CommandInfo>> = None;
 static mut GET_PLUGIN_NAME: Option<extern "thiscall" fn(*const SfPluginInfo) -> StdString> = None;
@@ -100,12 +112,30 @@ macro_rules! def_fn {
 }
 
 pub unsafe fn initialize() -> Result<(), Error> {
-    let handle = GetModuleHandleW(w!("SAMPFUNCS.asi"))?;
+    if INITIALIZED {
+        return Ok(());
+    }
+
+    let handle = match utils::get_module_handle("SAMPFUNCS.asi") {
+        Ok(handle) => handle,
+        Err(e) => {
+            FAILED = !matches!(e, Error::ModuleNotLoaded(_));
+            return Err(e);
+        }
+    };
 
     def_fn!(handle, GET_CHAT_COMMANDS, "?getChatCommands@SAMPFUNCS@@QAE?AV?$vector@UstCommandInfo@@V?$allocator@UstCommandInfo@@@std@@@std@@XZ");
     def_fn!(handle, GET_PLUGIN_NAME, "?getPluginName@SFPluginInfo@@QAE?AV?$basic_string@DU?$char_traits@D@std@@V?$allocator@D@2@@std@@XZ");
     def_fn!(handle, GET_SCM_THREAD_NAME, "?GetThreadName@CScriptThread@@QAE?AV?$basic_string@DU?$char_traits@D@std@@V?$allocator@D@2@@std@@XZ");
 
+    CRT_FREE = utils::find_import(handle.0 as usize, cppstd::is_msvc_crt, "free")
+        .map(|f| std::mem::transmute(f));
+    if CRT_FREE.is_none() {
+        eprintln!(
+            "sampfuncs::initialize: MSVC CRT free is not imported, returned containers will leak"
+        );
+    }
+
     INITIALIZED = true;
     Ok(())
 }
@@ -114,11 +144,50 @@ pub fn is_initialized() -> bool {
     unsafe { INITIALIZED }
 }
 
+/// The module is present, but could not be used.
+pub fn has_failed() -> bool {
+    unsafe { FAILED }
+}
+
+/// Command copied out of SAMPFUNCS memory.
+pub struct ChatCommand {
+    pub name: String,
+    pub owner_type: CommandType,
+    pub owner_name: String,
+}
+
 pub struct SampFuncs {}
 
 impl SampFuncs {
-    pub fn get_chat_commands() -> StdVector<CommandInfo> {
+    // The returned vector is a copy owned by the caller, its buffer and the owners it points to
+    // are only guaranteed to be valid right after the call, so everything is copied at once.
+    pub fn get_chat_commands() -> Vec<ChatCommand> {
         let get_chat_commands = unsafe { GET_CHAT_COMMANDS.unwrap() };
-        get_chat_commands()
+        let sf_cmds: StdVector<CommandInfo> = get_chat_commands();
+
+        let commands = sf_cmds
+            .into_iter()
+            .map(|cmd| ChatCommand {
+                name: cmd.name.to_string(),
+                owner_type: cmd.owner_type,
+                owner_name: match cmd.owner() {
+                    CmdOwner::Nope => "unknown".to_string(),
+                    CmdOwner::Script(s) => s.thread_name().trim_end().to_string() + ".cs",
+                    CmdOwner::Plugin(p) => p.plugin_name(),
+                },
+            })
+            .collect();
+
+        // Same as the destructor of std::vector<stCommandInfo>, only the names own memory.
+        if let Some(free) = unsafe { CRT_FREE } {
+            unsafe {
+                for cmd in &sf_cmds {
+                    cmd.name.free_buffer(free);
+                }
+                sf_cmds.free_buffer(free);
+            }
+        }
+
+        commands
     }
 }
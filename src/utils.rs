@@ -9,10 +9,23 @@
  *
  *****************************************************************************/
 
-use core::ffi::c_void;
+use core::ffi::{c_char, c_void};
+use std::ffi::CStr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+use local_encoding::{windows::EncoderCodePage, Encoder};
+
+use crate::errors::Error;
 use windows::Win32::{
-    Foundation::{CloseHandle, INVALID_HANDLE_VALUE},
+    Foundation::{
+        CloseHandle, GlobalFree, ERROR_MOD_NOT_FOUND, HANDLE, HMODULE, HWND, INVALID_HANDLE_VALUE,
+        MAX_PATH,
+    },
+    Globalization::IsValidCodePage,
     System::{
+        DataExchange::{CloseClipboard, EmptyClipboard, OpenClipboard, SetClipboardData},
         Diagnostics::{
             Debug::IMAGE_NT_HEADERS32,
             ToolHelp::{
@@ -20,12 +33,88 @@ use windows::Win32::{
                 TH32CS_SNAPMODULE,
             },
         },
-        Memory::{VirtualProtect, PAGE_EXECUTE_READWRITE},
+        LibraryLoader::{GetModuleFileNameW, GetModuleHandleW},
+        Memory::{
+            GlobalAlloc, GlobalLock, GlobalUnlock, VirtualAlloc, VirtualFree, VirtualProtect,
+            VirtualQuery, GMEM_MOVEABLE, MEMORY_BASIC_INFORMATION, MEM_COMMIT, MEM_RELEASE,
+            MEM_RESERVE, PAGE_EXECUTE, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE,
+            PAGE_EXECUTE_WRITECOPY, PAGE_GUARD, PAGE_NOACCESS,
+        },
+        Ole::CF_UNICODETEXT,
+        SystemInformation::GetWindowsDirectoryW,
         SystemServices::IMAGE_DOS_HEADER,
         Threading::GetCurrentProcessId,
     },
 };
 
+fn until_nul<T: Default + PartialEq>(units: &[T]) -> &[T] {
+    let nul = T::default();
+    let end = units.iter().position(|u| *u == nul).unwrap_or(units.len());
+    &units[..end]
+}
+
+// 0 for the system code page.
+static CODE_PAGE: AtomicU32 = AtomicU32::new(0);
+// Fallback for a snapshot taken while a module is being loaded.
+static LAST_MODULE_LIST: Mutex<Option<ModuleList>> = Mutex::new(None);
+
+/// Code page of SA-MP strings, 0 for the system one. Returns false and keeps the current one if
+/// the code page is not installed.
+pub fn set_ansi_code_page(code_page: u32) -> bool {
+    if code_page != 0 && !unsafe { IsValidCodePage(code_page) }.as_bool() {
+        return false;
+    }
+    CODE_PAGE.store(code_page, Ordering::Relaxed);
+    true
+}
+
+/// Decodes a string in the configured code page, e.g. Windows-1251, up to the first nul.
+pub fn decode_ansi(bytes: &[u8]) -> String {
+    decode_ansi_with(CODE_PAGE.load(Ordering::Relaxed), bytes)
+}
+
+// 0 is CP_ACP, the system code page.
+fn decode_ansi_with(code_page: u32, bytes: &[u8]) -> String {
+    let bytes = until_nul(bytes);
+    if bytes.is_ascii() {
+        return String::from_utf8_lossy(bytes).into_owned();
+    }
+    EncoderCodePage(code_page)
+        .to_string(bytes)
+        .unwrap_or_else(|_| String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Same as `decode_ansi`, null is decoded as an empty string.
+pub unsafe fn decode_ansi_ptr(ptr: *const c_char) -> String {
+    if ptr.is_null() {
+        return String::new();
+    }
+    decode_ansi(CStr::from_ptr(ptr).to_bytes())
+}
+
+/// Encodes a string in the configured code page, characters that can't be represented are replaced.
+pub fn encode_ansi(text: &str) -> Vec<u8> {
+    encode_ansi_with(CODE_PAGE.load(Ordering::Relaxed), text)
+}
+
+fn encode_ansi_with(code_page: u32, text: &str) -> Vec<u8> {
+    if text.is_ascii() {
+        return text.as_bytes().to_vec();
+    }
+    EncoderCodePage(code_page)
+        .to_bytes(text)
+        .unwrap_or_else(|_| text.as_bytes().to_vec())
+}
+
+pub fn decode_utf16(units: &[u16]) -> String {
+    String::from_utf16_lossy(until_nul(units))
+}
+
+/// Length of a wide string, or `max` if there is no terminator among the first `max` units.
+pub unsafe fn wcsnlen(ptr: *const u16, max: usize) -> usize {
+    (0..max).find(|&i| *ptr.add(i) == 0).unwrap_or(max)
+}
+
 pub fn get_entry_point(base_address: usize) -> u32 {
     unsafe {
         let dos_header = *(base_address as *const IMAGE_DOS_HEADER);
@@ -36,22 +125,298 @@ pub fn get_entry_point(base_address: usize) -> u32 {
     }
 }
 
-pub unsafe fn write_memory<T>(address: usize, value: T) {
+/// Finds `call rel32` instructions in the code section of the module that call `target`.
+pub fn find_call_sites(base_address: usize, target: usize) -> Vec<usize> {
+    let (start, size) = unsafe {
+        let dos_header = *(base_address as *const IMAGE_DOS_HEADER);
+        let nt_headers =
+            *((base_address + (dos_header.e_lfanew as usize)) as *const IMAGE_NT_HEADERS32);
+        (
+            base_address + nt_headers.OptionalHeader.BaseOfCode as usize,
+            nt_headers.OptionalHeader.SizeOfCode as usize,
+        )
+    };
+
+    let code = unsafe { std::slice::from_raw_parts(start as *const u8, size) };
+    let mut sites = Vec::new();
+    for (i, window) in code.windows(5).enumerate() {
+        if window[0] != 0xE8 {
+            continue;
+        }
+        let relative = i32::from_le_bytes([window[1], window[2], window[3], window[4]]);
+        let address = start + i;
+        if address.wrapping_add(5).wrapping_add(relative as usize) == target {
+            sites.push(address);
+        }
+    }
+    sites
+}
+
+/// Address an imported function of the module has been bound to, looked up by name
+/// among the imports from the DLLs accepted by `dll`.
+pub fn find_import(
+    base_address: usize,
+    dll: impl Fn(&str) -> bool,
+    function: &str,
+) -> Option<usize> {
+    const IMAGE_DIRECTORY_ENTRY_IMPORT: usize = 1;
+    const IMAGE_ORDINAL_FLAG32: u32 = 0x8000_0000;
+
+    unsafe {
+        let dos_header = *(base_address as *const IMAGE_DOS_HEADER);
+        let nt_headers =
+            *((base_address + (dos_header.e_lfanew as usize)) as *const IMAGE_NT_HEADERS32);
+        let directory = nt_headers.OptionalHeader.DataDirectory[IMAGE_DIRECTORY_ENTRY_IMPORT];
+        if directory.VirtualAddress == 0 {
+            return None;
+        }
+
+        // IMAGE_IMPORT_DESCRIPTOR: OriginalFirstThunk, TimeDateStamp, ForwarderChain, Name, FirstThunk.
+        let mut descriptor = (base_address + directory.VirtualAddress as usize) as *const [u32; 5];
+        while (*descriptor)[3] != 0 {
+            let [original_first_thunk, _, _, dll_name, first_thunk] = *descriptor;
+            descriptor = descriptor.add(1);
+            let dll_name = CStr::from_ptr((base_address + dll_name as usize) as *const c_char);
+            if !dll(&dll_name.to_string_lossy()) {
+                continue;
+            }
+
+            let names = if original_first_thunk != 0 {
+                original_first_thunk
+            } else {
+                first_thunk
+            };
+            let names = (base_address + names as usize) as *const u32;
+            let addresses = (base_address + first_thunk as usize) as *const usize;
+
+            let mut i = 0;
+            while *names.add(i) != 0 {
+                let thunk = *names.add(i);
+                if thunk & IMAGE_ORDINAL_FLAG32 == 0 {
+                    // IMAGE_IMPORT_BY_NAME: Hint, then the name.
+                    let name = CStr::from_ptr((base_address + thunk as usize + 2) as *const c_char);
+                    if name.to_bytes() == function.as_bytes() {
+                        return Some(*addresses.add(i));
+                    }
+                }
+                i += 1;
+            }
+        }
+    }
+
+    None
+}
+
+/// Absence of the module is reported as `ModuleNotLoaded`, so it can be told apart from a real failure.
+pub fn get_module_handle(name: &'static str) -> Result<HMODULE, Error> {
+    let wide = windows::core::HSTRING::from(name);
+    unsafe { GetModuleHandleW(windows::core::PCWSTR(wide.as_ptr())) }.map_err(|e| {
+        if e.code() == ERROR_MOD_NOT_FOUND.to_hresult() {
+            Error::ModuleNotLoaded(name)
+        } else {
+            Error::WinApiError(e)
+        }
+    })
+}
+
+pub fn get_module_directory(module: HMODULE) -> Option<PathBuf> {
+    let mut buffer = [0u16; MAX_PATH as usize];
+    let len = unsafe { GetModuleFileNameW(module, &mut buffer) } as usize;
+    if len == 0 || len >= buffer.len() {
+        return None;
+    }
+
+    let path = PathBuf::from(String::from_utf16_lossy(&buffer[..len]));
+    path.parent().map(|p| p.to_path_buf())
+}
+
+pub fn get_windows_directory() -> Option<PathBuf> {
+    let mut buffer = [0u16; MAX_PATH as usize];
+    let len = unsafe { GetWindowsDirectoryW(Some(&mut buffer)) } as usize;
+    if len == 0 || len >= buffer.len() {
+        return None;
+    }
+    Some(PathBuf::from(String::from_utf16_lossy(&buffer[..len])))
+}
+
+fn query_memory(address: usize) -> Option<MEMORY_BASIC_INFORMATION> {
+    let mut info = MEMORY_BASIC_INFORMATION::default();
+    let size = unsafe {
+        VirtualQuery(
+            Some(address as *const c_void),
+            &mut info,
+            std::mem::size_of::<MEMORY_BASIC_INFORMATION>(),
+        )
+    };
+
+    if size == 0 || info.State != MEM_COMMIT {
+        None
+    } else {
+        Some(info)
+    }
+}
+
+pub fn is_readable_address(address: usize) -> bool {
+    match query_memory(address) {
+        Some(info) => info.Protect.0 & (PAGE_NOACCESS.0 | PAGE_GUARD.0) == 0,
+        None => false,
+    }
+}
+
+/// Bytes that can be read from the address up to the end of its memory region, 0 if none.
+pub fn readable_size(address: usize) -> usize {
+    match query_memory(address) {
+        Some(info) if info.Protect.0 & (PAGE_NOACCESS.0 | PAGE_GUARD.0) == 0 => {
+            let end = info.BaseAddress as usize + info.RegionSize;
+            end.saturating_sub(address)
+        }
+        _ => 0,
+    }
+}
+
+pub fn is_executable_address(address: usize) -> bool {
+    const EXECUTABLE: u32 =
+        PAGE_EXECUTE.0 | PAGE_EXECUTE_READ.0 | PAGE_EXECUTE_READWRITE.0 | PAGE_EXECUTE_WRITECOPY.0;
+
+    match query_memory(address) {
+        Some(info) => info.Protect.0 & EXECUTABLE != 0,
+        None => false,
+    }
+}
+
+/// Counts consecutive virtual methods that point to code, up to `max`.
+pub fn count_vtable_methods(object: *const c_void, max: usize) -> usize {
+    if object.is_null() || !is_readable_address(object as usize) {
+        return 0;
+    }
+
+    let vtable = unsafe { *(object as *const *const usize) };
+    let mut count = 0;
+    while count < max {
+        let entry = unsafe { vtable.add(count) };
+        if !is_readable_address(entry as usize) || !is_executable_address(unsafe { *entry }) {
+            break;
+        }
+        count += 1;
+    }
+    count
+}
+
+/// Nothing is written if the protection can't be changed, e.g. on pages guarded by an anti-cheat.
+pub unsafe fn write_memory<T>(address: usize, value: T) -> Result<(), Error> {
     let size = std::mem::size_of::<T>();
     let mut vp = PAGE_EXECUTE_READWRITE;
-    VirtualProtect(address as *const c_void, size, vp, &mut vp).unwrap();
+    VirtualProtect(address as *const c_void, size, vp, &mut vp)?;
     std::ptr::write(address as *mut T, value);
-    VirtualProtect(address as *const c_void, size, vp, &mut vp).unwrap();
+    // The value is written anyway, the page is just left writable.
+    if let Err(e) = VirtualProtect(address as *const c_void, size, vp, &mut vp) {
+        eprintln!("utils::write_memory: {:#X}: {}", address, e);
+    }
+    Ok(())
 }
 
-pub unsafe fn replace_data_and_return_original<T>(address: usize, value: T) -> T {
+pub unsafe fn replace_data_and_return_original<T>(address: usize, value: T) -> Result<T, Error> {
+    // Reading it first would crash on unmapped memory.
+    if !is_readable_address(address) {
+        return Err(Error::InvalidAddress(address));
+    }
     let original = std::ptr::read(address as *const T);
-    write_memory(address, value);
-    original
+    write_memory(address, value)?;
+    Ok(original)
+}
+
+pub unsafe fn patch_call_address(address: usize, value: usize) -> Result<(), Error> {
+    write_memory(address + 1, value - address - 1 - 4)
+}
+
+const JMP_SIZE: usize = 5;
+
+fn jump(from: usize, to: usize) -> [u8; JMP_SIZE] {
+    let relative = to.wrapping_sub(from + JMP_SIZE) as u32;
+    let mut code = [0xE9; JMP_SIZE];
+    code[1..].copy_from_slice(&relative.to_le_bytes());
+    code
+}
+
+unsafe fn modrm_length(modrm: *const u8) -> usize {
+    let mode = *modrm >> 6;
+    let rm = *modrm & 7;
+    if mode == 3 {
+        return 1;
+    }
+
+    let mut length = 1;
+    if rm == 4 {
+        // SIB, without a base register with mode 0.
+        length += 1;
+        if mode == 0 && *modrm.add(1) & 7 == 5 {
+            length += 4;
+        }
+    } else if mode == 0 && rm == 5 {
+        length += 4;
+    }
+    length
+        + match mode {
+            1 => 1,
+            2 => 4,
+            _ => 0,
+        }
 }
 
-pub unsafe fn patch_call_address(address: usize, value: usize) {
-    write_memory(address + 1, value - address - 1 - 4);
+// Only instructions usual for function prologues. Relative jumps and calls can't be moved,
+// so they are unknown as well as anything else.
+unsafe fn instruction_length(code: *const u8) -> Option<usize> {
+    Some(match *code {
+        // push/pop reg
+        0x50..=0x5F => 1,
+        // push imm8
+        0x6A => 2,
+        // push imm32, mov eax, [moffs32], mov reg, imm32
+        0x68 | 0xA1 | 0xB8..=0xBF => 5,
+        // fs: used to set up SEH
+        0x64 => 1 + instruction_length(code.add(1))?,
+        // add, sub, xor, cmp, test, mov, lea with ModRM
+        0x03 | 0x2B | 0x31 | 0x33 | 0x3B | 0x85 | 0x89 | 0x8B | 0x8D => {
+            1 + modrm_length(code.add(1))
+        }
+        0x83 => 1 + modrm_length(code.add(1)) + 1,
+        0x81 | 0xC7 => 1 + modrm_length(code.add(1)) + 4,
+        _ => return None,
+    })
+}
+
+/// Redirects the function to `hook` by overwriting its beginning with a jump.
+/// Returns the address of a trampoline that calls the original function.
+/// Fails rather than guesses if the beginning has unknown instructions, e.g. a hook of another plugin.
+pub unsafe fn hook_function(target: usize, hook: usize) -> Result<usize, Error> {
+    let mut stolen = 0;
+    while stolen < JMP_SIZE {
+        stolen += instruction_length((target + stolen) as *const u8)
+            .ok_or(Error::UnsupportedCode(target))?;
+    }
+
+    let trampoline = VirtualAlloc(
+        None,
+        stolen + JMP_SIZE,
+        MEM_COMMIT | MEM_RESERVE,
+        PAGE_EXECUTE_READWRITE,
+    ) as usize;
+    if trampoline == 0 {
+        return Err(windows::core::Error::from_win32().into());
+    }
+
+    std::ptr::copy_nonoverlapping(target as *const u8, trampoline as *mut u8, stolen);
+    std::ptr::write(
+        (trampoline + stolen) as *mut [u8; JMP_SIZE],
+        jump(trampoline + stolen, target + stolen),
+    );
+
+    if let Err(e) = write_memory(target, jump(target, hook)) {
+        let _ = VirtualFree(trampoline as *mut c_void, 0, MEM_RELEASE);
+        return Err(e);
+    }
+
+    Ok(trampoline)
 }
 
 pub unsafe fn extract_call_target_address(address: usize) -> usize {
@@ -59,53 +424,221 @@ pub unsafe fn extract_call_target_address(address: usize) -> usize {
     address + relative + 1 + 4
 }
 
+#[derive(Clone)]
+struct ModuleRange {
+    base: usize,
+    size: usize,
+    name: String,
+}
+
+/// Modules loaded in the process, taken once and reused for any number of lookups.
+#[derive(Clone)]
+pub struct ModuleList {
+    modules: Vec<ModuleRange>,
+}
+
+impl ModuleList {
+    /// Snapshots fail with ERROR_BAD_LENGTH while a module is being loaded. It is called
+    /// on the game thread, so instead of waiting to retry the last good snapshot is returned.
+    pub fn snapshot() -> Option<Self> {
+        let mut last = LAST_MODULE_LIST.lock().ok();
+        match Self::try_snapshot() {
+            Ok(list) => {
+                if let Some(last) = last.as_mut() {
+                    **last = Some(list.clone());
+                }
+                Some(list)
+            }
+            Err(e) => {
+                eprintln!("utils::ModuleList::snapshot: {}", e);
+                last.and_then(|last| last.clone())
+            }
+        }
+    }
+
+    // Modules listed before Module32NextW fails are kept, better some names than none.
+    fn try_snapshot() -> windows::core::Result<Self> {
+        let snapshot =
+            unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPMODULE, GetCurrentProcessId()) }?;
+        if snapshot == INVALID_HANDLE_VALUE {
+            return Err(windows::core::Error::from_win32());
+        }
+
+        let mut module_entry32 = MODULEENTRY32W::default();
+        module_entry32.dwSize = std::mem::size_of::<MODULEENTRY32W>() as u32;
+
+        let mut modules = Vec::new();
+        let first = unsafe { Module32FirstW(snapshot, &mut module_entry32) };
+        if first.is_ok() {
+            loop {
+                modules.push(ModuleRange {
+                    base: module_entry32.modBaseAddr as usize,
+                    size: module_entry32.modBaseSize as usize,
+                    // The buffer is padded after the nul.
+                    name: decode_utf16(&module_entry32.szModule),
+                });
+
+                if unsafe { Module32NextW(snapshot, &mut module_entry32) }.is_err() {
+                    break;
+                }
+            }
+        }
+
+        unsafe {
+            let _ = CloseHandle(snapshot);
+        }
+
+        first.map(|()| Self { modules })
+    }
+
+    /// Name of the module that contains the address.
+    pub fn find(&self, address: *const c_void) -> Option<&str> {
+        self.find_with_base(address).map(|(name, _)| name)
+    }
+
+    /// Name and base address of the module that contains the address.
+    pub fn find_with_base(&self, address: *const c_void) -> Option<(&str, usize)> {
+        let address = address as usize;
+        self.modules
+            .iter()
+            .find(|m| address > m.base && address < m.base + m.size)
+            .map(|m| (m.name.as_str(), m.base))
+    }
+}
+
+/// Name of the module of each address. Takes a snapshot, prefer `ModuleList` for repeated lookups.
+// The signature stays as it was, the plugin itself uses ModuleList.
+#[allow(dead_code)]
 pub fn find_module_name_that_owns_address_list(
     addresses: &[*const c_void],
 ) -> Option<Vec<Option<String>>> {
-    let snapshot =
-        unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPMODULE, GetCurrentProcessId()) }.unwrap();
-    if snapshot == INVALID_HANDLE_VALUE {
-        return None;
+    let modules = ModuleList::snapshot()?;
+    Some(
+        addresses
+            .iter()
+            .map(|&address| modules.find(address).map(|name| name.to_string()))
+            .collect(),
+    )
+}
+
+/// Name and base address of the module of each address.
+pub fn find_module_name_that_owns_address_list_with_base(
+    addresses: &[*const c_void],
+) -> Option<Vec<Option<(String, usize)>>> {
+    let modules = ModuleList::snapshot()?;
+    Some(
+        addresses
+            .iter()
+            .map(|&address| {
+                modules
+                    .find_with_base(address)
+                    .map(|(name, base)| (name.to_string(), base))
+            })
+            .collect(),
+    )
+}
+
+pub fn set_clipboard_text(owner: HWND, text: &str) -> Result<(), Error> {
+    let wide: Vec<u16> = text.encode_utf16().chain(std::iter::once(0)).collect();
+    let size = wide.len() * std::mem::size_of::<u16>();
+
+    unsafe {
+        OpenClipboard(owner)?;
+
+        let result = (|| -> Result<(), Error> {
+            EmptyClipboard()?;
+
+            let mem = GlobalAlloc(GMEM_MOVEABLE, size)?;
+            let buffer = GlobalLock(mem) as *mut u16;
+            if buffer.is_null() {
+                let _ = GlobalFree(mem);
+                return Err(windows::core::Error::from_win32().into());
+            }
+            std::ptr::copy_nonoverlapping(wide.as_ptr(), buffer, wide.len());
+            let _ = GlobalUnlock(mem);
+
+            // On success the system owns the memory.
+            if let Err(e) = SetClipboardData(CF_UNICODETEXT.0 as u32, HANDLE(mem.0 as _)) {
+                let _ = GlobalFree(mem);
+                return Err(e.into());
+            }
+
+            Ok(())
+        })();
+
+        let _ = CloseClipboard();
+        result
     }
+}
 
-    let mut module_entry32 = MODULEENTRY32W::default();
-    module_entry32.dwSize = std::mem::size_of::<MODULEENTRY32W>() as u32;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if unsafe { Module32FirstW(snapshot, &mut module_entry32) }.is_err() {
+    #[test]
+    fn patching_unmapped_memory_fails() {
         unsafe {
-            CloseHandle(snapshot).unwrap();
+            assert!(write_memory(0, 0u32).is_err());
+            assert!(matches!(
+                replace_data_and_return_original(0, 0u32),
+                Err(Error::InvalidAddress(0))
+            ));
         }
-        return None;
     }
 
-    let mut module_names = vec![None; addresses.len()];
-
-    loop {
-        for (index, &address) in addresses.iter().enumerate() {
-            let module_name = &mut module_names[index];
-            if module_name.is_none() {
-                let address = address as *const u8;
-                if address > module_entry32.modBaseAddr
-                    && address
-                        < unsafe {
-                            module_entry32
-                                .modBaseAddr
-                                .add(module_entry32.modBaseSize as usize)
-                        }
-                {
-                    *module_name = Some(String::from_utf16_lossy(&module_entry32.szModule));
-                }
-            }
-        }
+    #[test]
+    fn readable_size_ends_with_the_region() {
+        let buffer = [0u8; 64];
+        assert!(readable_size(buffer.as_ptr() as usize) >= buffer.len());
+        assert_eq!(readable_size(0), 0);
+    }
 
-        if unsafe { Module32NextW(snapshot, &mut module_entry32) }.is_err() {
-            break;
+    #[test]
+    fn decode_ansi_stops_at_nul() {
+        assert_eq!(decode_ansi(b"/help"), "/help");
+        assert_eq!(decode_ansi(b"/help\0junk"), "/help");
+        assert_eq!(decode_ansi(b""), "");
+    }
+
+    #[test]
+    fn decode_ansi_ptr_reads_a_c_string() {
+        let text = c"/pm 1 hi";
+        unsafe {
+            assert_eq!(decode_ansi_ptr(text.as_ptr()), "/pm 1 hi");
+            assert_eq!(decode_ansi_ptr(std::ptr::null()), "");
         }
     }
 
-    unsafe {
-        CloseHandle(snapshot).unwrap();
+    #[test]
+    fn decode_ansi_uses_the_code_page() {
+        // "/привет" in Windows-1251.
+        let bytes = [b'/', 0xEF, 0xF0, 0xE8, 0xE2, 0xE5, 0xF2];
+        assert_eq!(decode_ansi_with(1251, &bytes), "/привет");
+        assert_eq!(encode_ansi_with(1251, "/привет"), bytes);
+    }
+
+    #[test]
+    fn module_name_is_cut_at_nul() {
+        // szModule of MODULEENTRY32W, with what was left from a longer name after the nul.
+        let mut module = MODULEENTRY32W::default().szModule;
+        module.fill(b'x' as u16);
+        for (unit, c) in module.iter_mut().zip("samp.dll\0".encode_utf16()) {
+            *unit = c;
+        }
+        assert_eq!(decode_utf16(&module), "samp.dll");
+
+        let unterminated = [b'y' as u16; 8];
+        assert_eq!(decode_utf16(&unterminated), "yyyyyyyy");
     }
 
-    Some(module_names)
+    #[test]
+    fn wcsnlen_stops_at_max() {
+        let terminated = [b'a' as u16, b'b' as u16, 0, b'c' as u16];
+        let unterminated = [b'a' as u16; 4];
+        unsafe {
+            assert_eq!(wcsnlen(terminated.as_ptr(), terminated.len()), 2);
+            assert_eq!(wcsnlen(unterminated.as_ptr(), unterminated.len()), 4);
+            assert_eq!(wcsnlen(unterminated.as_ptr(), 0), 0);
+        }
+    }
 }